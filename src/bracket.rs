@@ -0,0 +1,191 @@
+use crate::PositionType;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// How the take-profit or cut-loss price of a position is combined with
+/// the one of a fill that increases it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BracketMergePolicy {
+    KeepExisting,
+    /// Use the level of the fill, even when it has none.
+    Replace,
+    /// Average of both levels weighted by amount. A fill without a level
+    /// clears it, as increases did before the policies were added.
+    #[default]
+    WeightedAverage,
+    /// The level closer to the price: the nearer take-profit, the tighter
+    /// cut-loss.
+    MostConservative,
+    /// The level further from the price: the farther take-profit, the
+    /// wider cut-loss.
+    MostAggressive,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BracketKind {
+    TakeProfit,
+    CutLoss,
+}
+
+impl BracketMergePolicy {
+    /// Merges the level of the position with the one of an increase. Except
+    /// for `Replace` and `WeightedAverage`, a missing level leaves the other
+    /// one in place.
+    pub(crate) fn merge(
+        &self,
+        kind: BracketKind,
+        position_type: &PositionType,
+        current: Option<Decimal>,
+        current_amount: Decimal,
+        new: Option<Decimal>,
+        amount: Decimal,
+    ) -> Option<Decimal> {
+        if *self == BracketMergePolicy::Replace {
+            return new;
+        }
+        if *self == BracketMergePolicy::WeightedAverage && new.is_none() {
+            return None;
+        }
+        let (current, new) = match (current, new) {
+            (Some(current), Some(new)) => (current, new),
+            (current, new) => return current.or(new),
+        };
+
+        // Whether the conservative level is the lower one
+        let lower_is_conservative = matches!(
+            (kind, position_type),
+            (BracketKind::TakeProfit, PositionType::Long)
+                | (BracketKind::CutLoss, PositionType::Short)
+        );
+
+        match self {
+            BracketMergePolicy::KeepExisting => Some(current),
+            BracketMergePolicy::Replace => Some(new),
+            BracketMergePolicy::WeightedAverage => {
+                Some((current * current_amount + new * amount) / (current_amount + amount))
+            }
+            BracketMergePolicy::MostConservative if lower_is_conservative => Some(current.min(new)),
+            BracketMergePolicy::MostConservative => Some(current.max(new)),
+            BracketMergePolicy::MostAggressive if lower_is_conservative => Some(current.max(new)),
+            BracketMergePolicy::MostAggressive => Some(current.min(new)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    /// Take-profit and cut-loss after adding 3 at `new` to 1 at `current`.
+    fn merged(
+        policy: BracketMergePolicy,
+        position_type: PositionType,
+        current: (Decimal, Decimal),
+        new: (Decimal, Decimal),
+    ) -> (Option<Decimal>, Option<Decimal>) {
+        let merge = |kind, current, new| {
+            policy.merge(
+                kind,
+                &position_type,
+                Some(current),
+                dec!(1),
+                Some(new),
+                dec!(3),
+            )
+        };
+        (
+            merge(BracketKind::TakeProfit, current.0, new.0),
+            merge(BracketKind::CutLoss, current.1, new.1),
+        )
+    }
+
+    #[test]
+    fn long_levels_are_merged_per_policy() {
+        let cases = [
+            (BracketMergePolicy::KeepExisting, dec!(110), dec!(90)),
+            (BracketMergePolicy::Replace, dec!(120), dec!(95)),
+            (
+                BracketMergePolicy::WeightedAverage,
+                dec!(117.5),
+                dec!(93.75),
+            ),
+            (BracketMergePolicy::MostConservative, dec!(110), dec!(95)),
+            (BracketMergePolicy::MostAggressive, dec!(120), dec!(90)),
+        ];
+        for (policy, take_profit, cut_loss) in cases {
+            assert_eq!(
+                merged(
+                    policy,
+                    PositionType::Long,
+                    (dec!(110), dec!(90)),
+                    (dec!(120), dec!(95))
+                ),
+                (Some(take_profit), Some(cut_loss)),
+                "{:?}",
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn short_levels_are_merged_per_policy() {
+        let cases = [
+            (BracketMergePolicy::KeepExisting, dec!(90), dec!(110)),
+            (BracketMergePolicy::Replace, dec!(80), dec!(105)),
+            (
+                BracketMergePolicy::WeightedAverage,
+                dec!(82.5),
+                dec!(106.25),
+            ),
+            (BracketMergePolicy::MostConservative, dec!(90), dec!(105)),
+            (BracketMergePolicy::MostAggressive, dec!(80), dec!(110)),
+        ];
+        for (policy, take_profit, cut_loss) in cases {
+            assert_eq!(
+                merged(
+                    policy,
+                    PositionType::Short,
+                    (dec!(90), dec!(110)),
+                    (dec!(80), dec!(105))
+                ),
+                (Some(take_profit), Some(cut_loss)),
+                "{:?}",
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn missing_level_is_kept_except_by_replace_and_weighted_average() {
+        let kind = BracketKind::TakeProfit;
+        let long = PositionType::Long;
+        for policy in [
+            BracketMergePolicy::KeepExisting,
+            BracketMergePolicy::MostConservative,
+            BracketMergePolicy::MostAggressive,
+        ] {
+            assert_eq!(
+                policy.merge(kind, &long, Some(dec!(110)), dec!(1), None, dec!(1)),
+                Some(dec!(110))
+            );
+            assert_eq!(
+                policy.merge(kind, &long, None, dec!(1), Some(dec!(120)), dec!(1)),
+                Some(dec!(120))
+            );
+        }
+        for policy in [
+            BracketMergePolicy::Replace,
+            BracketMergePolicy::WeightedAverage,
+        ] {
+            assert_eq!(
+                policy.merge(kind, &long, Some(dec!(110)), dec!(1), None, dec!(1)),
+                None
+            );
+            assert_eq!(
+                policy.merge(kind, &long, None, dec!(1), Some(dec!(120)), dec!(1)),
+                Some(dec!(120))
+            );
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use std::{
+    env, fmt,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+};
+
+/// Source of the current time, in seconds since the Unix epoch.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> i64;
+}
+
+/// Wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        Utc::now().timestamp()
+    }
+}
+
+/// Clock that only moves when told to. Useful for fixed timestamps in tests.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicI64,
+}
+
+impl ManualClock {
+    pub fn new(timestamp: i64) -> Self {
+        Self {
+            now: AtomicI64::new(timestamp),
+        }
+    }
+
+    pub fn set(&self, timestamp: i64) {
+        self.now.store(timestamp, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, seconds: i64) {
+        self.now.fetch_add(seconds, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+/// Clock driven by the timestamps of replayed candles. Stale candles never
+/// move it backwards.
+#[derive(Debug, Default)]
+pub struct SimulatedClock {
+    now: AtomicI64,
+}
+
+impl SimulatedClock {
+    pub fn new(start: i64) -> Self {
+        Self {
+            now: AtomicI64::new(start),
+        }
+    }
+
+    pub fn on_candle(&self, timestamp: i64) {
+        self.now.fetch_max(timestamp, Ordering::SeqCst);
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Clone)]
+pub struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    pub fn new<C: Clock + 'static>(clock: C) -> Self {
+        Self(Arc::new(clock))
+    }
+
+    pub fn now(&self) -> i64 {
+        self.0.now()
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<C: Clock + 'static> From<Arc<C>> for SharedClock {
+    fn from(clock: Arc<C>) -> Self {
+        Self(clock)
+    }
+}
+
+impl From<Arc<dyn Clock>> for SharedClock {
+    fn from(clock: Arc<dyn Clock>) -> Self {
+        Self(clock)
+    }
+}
+
+/// Time zone used for formatted timestamps when none is configured: the
+/// `TIMEZONE_OFFSET` environment variable in seconds east of UTC, else UTC+1.
+pub(crate) fn default_time_zone() -> FixedOffset {
+    env::var("TIMEZONE_OFFSET")
+        .ok()
+        .and_then(|s| s.parse::<i32>().ok())
+        .and_then(FixedOffset::east_opt)
+        .unwrap_or_else(|| FixedOffset::east_opt(3600).unwrap())
+}
+
+pub(crate) fn format_time(time: DateTime<Utc>, time_zone: FixedOffset) -> String {
+    time.with_timezone(&time_zone)
+        .format("%Y-%m-%dT%H:%M:%S%z")
+        .to_string()
+}
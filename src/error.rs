@@ -0,0 +1,170 @@
+use crate::{OrderState, PositionState, PositionType};
+use rust_decimal::Decimal;
+use std::{error::Error, fmt};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PositionError {
+    InvalidState {
+        id: u32,
+        state: PositionState,
+        operation: &'static str,
+    },
+    InvalidTransition {
+        id: u32,
+        from: PositionState,
+        to: PositionState,
+    },
+    InvalidAmount {
+        id: u32,
+        amount: Decimal,
+    },
+    InvalidParameter {
+        id: u32,
+        name: &'static str,
+        reason: &'static str,
+    },
+    UnknownPosition {
+        id: u32,
+    },
+    DuplicatePosition {
+        id: u32,
+    },
+    OrderMismatch {
+        id: u32,
+        order_id: String,
+        position_id: Option<u32>,
+    },
+    SideMismatch {
+        id: u32,
+        order_id: String,
+        expected: PositionType,
+        actual: PositionType,
+    },
+    DuplicateFill {
+        id: u32,
+        fill_id: String,
+    },
+    UnknownFill {
+        id: u32,
+        fill_id: String,
+    },
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionError::InvalidState {
+                id,
+                state,
+                operation,
+            } => write!(
+                f,
+                "position[{}]: {} is not allowed in state {}",
+                id, operation, state
+            ),
+            PositionError::InvalidTransition { id, from, to } => {
+                write!(f, "position[{}]: cannot go from {} to {}", id, from, to)
+            }
+            PositionError::InvalidAmount { id, amount } => {
+                write!(f, "position[{}]: invalid amount {}", id, amount)
+            }
+            PositionError::InvalidParameter { id, name, reason } => {
+                write!(f, "position[{}]: {} {}", id, name, reason)
+            }
+            PositionError::UnknownPosition { id } => write!(f, "position[{}]: not found", id),
+            PositionError::DuplicatePosition { id } => {
+                write!(f, "position[{}]: already exists", id)
+            }
+            PositionError::OrderMismatch {
+                id,
+                order_id,
+                position_id,
+            } => write!(
+                f,
+                "position[{}]: order {} belongs to position {:?}",
+                id, order_id, position_id
+            ),
+            PositionError::SideMismatch {
+                id,
+                order_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "position[{}]: order {} is {} but {} was expected",
+                id, order_id, actual, expected
+            ),
+            PositionError::DuplicateFill { id, fill_id } => {
+                write!(f, "position[{}]: fill {} was already applied", id, fill_id)
+            }
+            PositionError::UnknownFill { id, fill_id } => {
+                write!(f, "position[{}]: fill {} was not applied", id, fill_id)
+            }
+        }
+    }
+}
+
+impl Error for PositionError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderError {
+    InvalidState {
+        id: String,
+        state: OrderState,
+        operation: &'static str,
+    },
+    InvalidAmount {
+        id: String,
+        amount: Decimal,
+    },
+    Overfill {
+        id: String,
+        unfilled_amount: Decimal,
+        filled_amount: Decimal,
+    },
+    InvalidParameter {
+        id: String,
+        name: &'static str,
+        reason: &'static str,
+    },
+    DuplicateFill {
+        id: String,
+        fill_id: String,
+    },
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderError::InvalidState {
+                id,
+                state,
+                operation,
+            } => write!(
+                f,
+                "order[{}]: {} is not allowed in state {}",
+                id, operation, state
+            ),
+            OrderError::InvalidAmount { id, amount } => {
+                write!(f, "order[{}]: invalid amount {}", id, amount)
+            }
+            OrderError::Overfill {
+                id,
+                unfilled_amount,
+                filled_amount,
+            } => write!(
+                f,
+                "order[{}]: fill of {} exceeds unfilled amount {}",
+                id, filled_amount, unfilled_amount
+            ),
+            OrderError::InvalidParameter { id, name, reason } => {
+                write!(f, "order[{}]: {} {}", id, name, reason)
+            }
+            OrderError::DuplicateFill { id, fill_id } => {
+                write!(f, "order[{}]: fill {} was already applied", id, fill_id)
+            }
+        }
+    }
+}
+
+impl Error for OrderError {}
@@ -0,0 +1,52 @@
+use debot_db::CandlePattern;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+pub const INDICATOR_SERIES_LEN: usize = 6;
+
+/// One indicator sampled over the strategy's lookback terms, in the order
+/// the caller supplied them (slot 0 first).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IndicatorSeries<T>([T; INDICATOR_SERIES_LEN]);
+
+impl<T: Copy> IndicatorSeries<T> {
+    pub fn new(values: [T; INDICATOR_SERIES_LEN]) -> Self {
+        Self(values)
+    }
+
+    pub fn get(&self, term: usize) -> Option<T> {
+        self.0.get(term).copied()
+    }
+
+    pub fn values(&self) -> &[T; INDICATOR_SERIES_LEN] {
+        &self.0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl<T> From<(T, T, T, T, T, T)> for IndicatorSeries<T> {
+    fn from(t: (T, T, T, T, T, T)) -> Self {
+        Self([t.0, t.1, t.2, t.3, t.4, t.5])
+    }
+}
+
+impl<T> From<IndicatorSeries<T>> for (T, T, T, T, T, T) {
+    fn from(series: IndicatorSeries<T>) -> Self {
+        let [a, b, c, d, e, f] = series.0;
+        (a, b, c, d, e, f)
+    }
+}
+
+/// Market indicators captured at a point in the life of a position.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct IndicatorSnapshot {
+    pub atr: IndicatorSeries<Decimal>,
+    pub adx: IndicatorSeries<Decimal>,
+    pub rsi: IndicatorSeries<Decimal>,
+    pub stochastic: IndicatorSeries<Decimal>,
+    pub price: IndicatorSeries<Decimal>,
+    pub candle_pattern: IndicatorSeries<CandlePattern>,
+}
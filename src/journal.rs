@@ -0,0 +1,149 @@
+use crate::{Position, PositionType, ReasonForClose, StopRule, TrailingStop, VolatilityStop};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// An execution reported by the exchange for a position.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Fill {
+    /// Exchange id of the execution. Fills with an id are applied once.
+    pub fill_id: Option<String>,
+    pub position_type: PositionType,
+    pub price: Decimal,
+    pub amount: Decimal,
+    pub asset_in_usd: Decimal,
+    pub fee: Decimal,
+    pub take_profit_price: Option<Decimal>,
+    pub cut_loss_price: Option<Decimal>,
+    pub current_price: Decimal,
+    /// Exchange sequence number, used to detect fills delivered late.
+    pub sequence: Option<u64>,
+    /// Exchange execution time in seconds, used when there is no sequence.
+    pub timestamp: Option<i64>,
+}
+
+impl Fill {
+    /// Whether the exchange executed `self` before `other`. Fills that
+    /// cannot be compared are treated as in order.
+    pub fn is_before(&self, other: &Fill) -> bool {
+        match (self.sequence, other.sequence) {
+            (Some(a), Some(b)) => a < b,
+            (None, None) => {
+                matches!((self.timestamp, other.timestamp), (Some(a), Some(b)) if a < b)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FillOutcome {
+    Applied,
+    /// Held back until the entry of the position is filled.
+    Buffered,
+    /// Arrived late and was applied at its place in the fill sequence.
+    Reordered,
+    /// Closed the position and opened the returned one with the rest, under
+    /// `InversionPolicy::Split`. The returned position needs its own id.
+    Split(Box<Position>),
+}
+
+/// Everything that changed the state of a position, in the order it
+/// happened. Replaying the events on a fresh position rebuilds it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PositionEvent {
+    Filled {
+        fill: Fill,
+        timestamp: i64,
+    },
+    CloseRequested {
+        reason: ReasonForClose,
+        timestamp: i64,
+    },
+    ClosingCancelled {
+        timestamp: i64,
+    },
+    EntryStarted {
+        timestamp: i64,
+    },
+    EntryFinished {
+        timestamp: i64,
+    },
+    EntryCancelled {
+        timestamp: i64,
+    },
+    ReduceRequested {
+        amount: Decimal,
+        reason: ReasonForClose,
+        timestamp: i64,
+    },
+    ReduceCancelled {
+        timestamp: i64,
+    },
+    FillBusted {
+        fill_id: String,
+        timestamp: i64,
+    },
+    Liquidated {
+        close_price: Decimal,
+        fee: Decimal,
+        do_liquidate: bool,
+        reason: Option<String>,
+        timestamp: i64,
+    },
+    /// `count` ticks in a row, the last at `timestamp`.
+    Tick {
+        count: u32,
+        timestamp: i64,
+    },
+    PriceUpdated {
+        price: Decimal,
+        timestamp: i64,
+    },
+    AtrUpdated {
+        atr: Decimal,
+        timestamp: i64,
+    },
+    StopRuleAdded {
+        rule: StopRule,
+        timestamp: i64,
+    },
+    VolatilityStopAdded {
+        stop: VolatilityStop,
+        timestamp: i64,
+    },
+    TrailingStopChanged {
+        previous: TrailingStop,
+        trailing_stop: TrailingStop,
+        timestamp: i64,
+    },
+    /// Offsets are in seconds east of UTC.
+    TimeZoneChanged {
+        previous: i32,
+        utc_offset_seconds: i32,
+        timestamp: i64,
+    },
+}
+
+impl PositionEvent {
+    pub fn timestamp(&self) -> i64 {
+        match self {
+            PositionEvent::Filled { timestamp, .. }
+            | PositionEvent::CloseRequested { timestamp, .. }
+            | PositionEvent::ClosingCancelled { timestamp }
+            | PositionEvent::EntryStarted { timestamp }
+            | PositionEvent::EntryFinished { timestamp }
+            | PositionEvent::EntryCancelled { timestamp }
+            | PositionEvent::ReduceRequested { timestamp, .. }
+            | PositionEvent::ReduceCancelled { timestamp }
+            | PositionEvent::FillBusted { timestamp, .. }
+            | PositionEvent::Liquidated { timestamp, .. }
+            | PositionEvent::Tick { timestamp, .. }
+            | PositionEvent::PriceUpdated { timestamp, .. }
+            | PositionEvent::AtrUpdated { timestamp, .. }
+            | PositionEvent::StopRuleAdded { timestamp, .. }
+            | PositionEvent::VolatilityStopAdded { timestamp, .. }
+            | PositionEvent::TrailingStopChanged { timestamp, .. }
+            | PositionEvent::TimeZoneChanged { timestamp, .. } => *timestamp,
+        }
+    }
+}
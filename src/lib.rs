@@ -1,8 +1,34 @@
+mod bracket;
+mod clock;
+mod error;
+mod indicator;
+mod journal;
+mod lot;
+mod order;
+mod position_book;
+mod position_builder;
 mod position_manager;
+mod stop_rule;
+mod take_profit;
+mod trailing_stop;
+mod volatility_stop;
 use std::fmt;
 
+pub use bracket::BracketMergePolicy;
+pub use clock::{Clock, ManualClock, SharedClock, SimulatedClock, SystemClock};
+pub use error::*;
+pub use indicator::*;
+pub use journal::*;
+pub use lot::{CostBasisMethod, Lot};
+pub use order::*;
+pub use position_book::*;
+pub use position_builder::*;
 pub use position_manager::*;
 use serde::{Deserialize, Serialize};
+pub use stop_rule::{StopModification, StopModificationReason, StopRule};
+pub use take_profit::{TakeProfitLevel, TakeProfitTarget};
+pub use trailing_stop::{TrailingActivation, TrailingStop, TrailingStopPolicy};
+pub use volatility_stop::VolatilityStop;
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 pub enum PositionType {
@@ -28,3 +54,11 @@ impl PositionType {
         }
     }
 }
+
+// Positions and orders are shared across tasks behind locks.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Position>();
+    assert_send_sync::<Order>();
+    assert_send_sync::<PositionBook>();
+};
@@ -0,0 +1,220 @@
+use crate::PositionType;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Order in which lots are consumed by a decrease, and the cost the
+/// realized PnL is measured against.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CostBasisMethod {
+    /// Realized PnL against the average open price. Lots are consumed
+    /// first in, first out.
+    #[default]
+    Average,
+    Fifo,
+    Lifo,
+    /// Least favourable entry first: the highest price for longs, the
+    /// lowest for shorts.
+    HighestCost,
+}
+
+/// Amount added to a position by one increase.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Lot {
+    pub position_type: PositionType,
+    pub open_price: Decimal,
+    pub amount: Decimal,
+    pub remaining_amount: Decimal,
+    pub open_timestamp: i64,
+    /// PnL realized by closing part of the lot, before fees.
+    pub realized_pnl: Decimal,
+}
+
+impl Lot {
+    pub(crate) fn new(
+        position_type: PositionType,
+        open_price: Decimal,
+        amount: Decimal,
+        open_timestamp: i64,
+    ) -> Self {
+        Self {
+            position_type,
+            open_price,
+            amount,
+            remaining_amount: amount,
+            open_timestamp,
+            realized_pnl: Decimal::ZERO,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.remaining_amount.is_zero()
+    }
+
+    fn sign(&self) -> Decimal {
+        match self.position_type {
+            PositionType::Long => Decimal::ONE,
+            PositionType::Short => Decimal::NEGATIVE_ONE,
+        }
+    }
+}
+
+/// Closes `amount` from the open lots at `close_price` and returns the
+/// realized PnL. `average_price` is the cost of `CostBasisMethod::Average`.
+pub(crate) fn close_lots(
+    lots: &mut [Lot],
+    method: CostBasisMethod,
+    amount: Decimal,
+    close_price: Decimal,
+    average_price: Decimal,
+) -> Decimal {
+    let mut order: Vec<usize> = (0..lots.len()).filter(|&i| lots[i].is_open()).collect();
+    match method {
+        CostBasisMethod::Average | CostBasisMethod::Fifo => {}
+        CostBasisMethod::Lifo => order.reverse(),
+        CostBasisMethod::HighestCost => {
+            order.sort_by_key(|&i| -lots[i].sign() * lots[i].open_price);
+        }
+    }
+
+    let mut unclosed = amount;
+    let mut realized_pnl = Decimal::ZERO;
+    for i in order {
+        if unclosed.is_zero() {
+            break;
+        }
+        let lot = &mut lots[i];
+        let closed = lot.remaining_amount.min(unclosed);
+        let cost = match method {
+            CostBasisMethod::Average => average_price,
+            _ => lot.open_price,
+        };
+        let pnl = (close_price - cost) * closed * lot.sign();
+
+        lot.remaining_amount -= closed;
+        lot.realized_pnl += pnl;
+        realized_pnl += pnl;
+        unclosed -= closed;
+    }
+
+    realized_pnl
+}
+
+/// Average open price of the open lots, if any.
+pub(crate) fn average_open_price(lots: &[Lot]) -> Option<Decimal> {
+    let amount: Decimal = lots.iter().map(|lot| lot.remaining_amount).sum();
+    if amount.is_zero() {
+        return None;
+    }
+    let cost: Decimal = lots
+        .iter()
+        .map(|lot| lot.open_price * lot.remaining_amount)
+        .sum();
+    Some(cost / amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn lots(position_type: PositionType) -> Vec<Lot> {
+        [dec!(100), dec!(110), dec!(120)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, price)| Lot::new(position_type.clone(), price, Decimal::ONE, i as i64))
+            .collect()
+    }
+
+    fn realized_pnl(
+        position_type: PositionType,
+        method: CostBasisMethod,
+        close_price: Decimal,
+    ) -> (Decimal, Vec<Decimal>) {
+        let mut lots = lots(position_type);
+        let average_price = average_open_price(&lots).unwrap();
+        let pnl = close_lots(&mut lots, method, dec!(1.5), close_price, average_price);
+        (pnl, lots.iter().map(|lot| lot.remaining_amount).collect())
+    }
+
+    #[test]
+    fn long_lots_are_closed_in_method_order() {
+        let cases = [
+            (
+                CostBasisMethod::Average,
+                dec!(30),
+                [dec!(0), dec!(0.5), dec!(1)],
+            ),
+            (
+                CostBasisMethod::Fifo,
+                dec!(40),
+                [dec!(0), dec!(0.5), dec!(1)],
+            ),
+            (
+                CostBasisMethod::Lifo,
+                dec!(20),
+                [dec!(1), dec!(0.5), dec!(0)],
+            ),
+            (
+                CostBasisMethod::HighestCost,
+                dec!(20),
+                [dec!(1), dec!(0.5), dec!(0)],
+            ),
+        ];
+        for (method, pnl, remaining) in cases {
+            assert_eq!(
+                realized_pnl(PositionType::Long, method, dec!(130)),
+                (pnl, remaining.to_vec()),
+                "{:?}",
+                method
+            );
+        }
+    }
+
+    #[test]
+    fn short_lots_are_closed_in_method_order() {
+        let cases = [
+            (
+                CostBasisMethod::Average,
+                dec!(30),
+                [dec!(0), dec!(0.5), dec!(1)],
+            ),
+            (
+                CostBasisMethod::Fifo,
+                dec!(20),
+                [dec!(0), dec!(0.5), dec!(1)],
+            ),
+            (
+                CostBasisMethod::Lifo,
+                dec!(40),
+                [dec!(1), dec!(0.5), dec!(0)],
+            ),
+            (
+                CostBasisMethod::HighestCost,
+                dec!(20),
+                [dec!(0), dec!(0.5), dec!(1)],
+            ),
+        ];
+        for (method, pnl, remaining) in cases {
+            assert_eq!(
+                realized_pnl(PositionType::Short, method, dec!(90)),
+                (pnl, remaining.to_vec()),
+                "{:?}",
+                method
+            );
+        }
+    }
+
+    #[test]
+    fn lot_pnl_adds_up_to_the_realized_pnl() {
+        for method in [
+            CostBasisMethod::Fifo,
+            CostBasisMethod::Lifo,
+            CostBasisMethod::HighestCost,
+        ] {
+            let mut lots = lots(PositionType::Long);
+            let pnl = close_lots(&mut lots, method, dec!(1.5), dec!(130), dec!(110));
+            let lot_pnl: Decimal = lots.iter().map(|lot| lot.realized_pnl).sum();
+            assert_eq!(lot_pnl, pnl);
+        }
+    }
+}
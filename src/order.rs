@@ -0,0 +1,562 @@
+use crate::{OrderError, PositionType, SharedClock};
+use chrono::Duration;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub enum OrderState {
+    #[default]
+    Open,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected(String),
+    Expired,
+}
+
+impl fmt::Display for OrderState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderState::Open => write!(f, "Open"),
+            OrderState::PartiallyFilled => write!(f, "PartiallyFilled"),
+            OrderState::Filled => write!(f, "Filled"),
+            OrderState::Cancelled => write!(f, "Cancelled"),
+            OrderState::Rejected(reason) => write!(f, "Rejected({})", reason),
+            OrderState::Expired => write!(f, "Expired"),
+        }
+    }
+}
+
+impl OrderState {
+    /// Whether the order can still be filled.
+    pub fn is_working(&self) -> bool {
+        matches!(self, OrderState::Open | OrderState::PartiallyFilled)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OrderFill {
+    pub fill_id: Option<String>,
+    pub price: Decimal,
+    pub amount: Decimal,
+    pub fee: Decimal,
+    pub timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OrderKind {
+    #[default]
+    Market,
+    Limit,
+    Stop,
+    StopLimit,
+}
+
+impl fmt::Display for OrderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderKind::Market => write!(f, "Market"),
+            OrderKind::Limit => write!(f, "Limit"),
+            OrderKind::Stop => write!(f, "Stop"),
+            OrderKind::StopLimit => write!(f, "StopLimit"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OrderType {
+    #[default]
+    OpenOrder,
+    CloseOrder,
+}
+
+/// Fields missing from data serialized by 3.x take their default value,
+/// except `amount`, which is taken from `unfilled_amount`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, remote = "Self")]
+pub struct Order {
+    id: String,
+    position_id: Option<u32>,
+    side: PositionType,
+    kind: OrderKind,
+    order_type: OrderType,
+    price: Option<Decimal>,
+    trigger_price: Option<Decimal>,
+    reduce_only: bool,
+    amount: Decimal,
+    unfilled_amount: Decimal,
+    state: OrderState,
+    fills: Vec<OrderFill>,
+    tick_count: u32,
+    entry_timeout_tick_count: u32,
+    entry_timeout_secs: Option<i64>,
+    created_timestamp: i64,
+    #[serde(skip)]
+    clock: SharedClock,
+}
+
+/// Named-parameter constructor for [`Order`]. Defaults to an unlinked
+/// market order opening a long position, without entry timeout.
+#[derive(Clone, Debug)]
+pub struct OrderBuilder {
+    id: String,
+    amount: Decimal,
+    position_id: Option<u32>,
+    side: PositionType,
+    kind: OrderKind,
+    order_type: OrderType,
+    price: Option<Decimal>,
+    trigger_price: Option<Decimal>,
+    reduce_only: bool,
+    entry_timeout_tick_count: u32,
+    entry_timeout: Option<Duration>,
+    clock: SharedClock,
+}
+
+impl OrderBuilder {
+    pub fn new(id: &str, amount: Decimal) -> Self {
+        Self {
+            id: id.to_owned(),
+            amount,
+            position_id: None,
+            side: PositionType::default(),
+            kind: OrderKind::default(),
+            order_type: OrderType::default(),
+            price: None,
+            trigger_price: None,
+            reduce_only: false,
+            entry_timeout_tick_count: u32::MAX,
+            entry_timeout: None,
+            clock: SharedClock::default(),
+        }
+    }
+
+    pub fn position_id(mut self, position_id: u32) -> Self {
+        self.position_id = Some(position_id);
+        self
+    }
+
+    pub fn side(mut self, side: PositionType) -> Self {
+        self.side = side;
+        self
+    }
+
+    pub fn kind(mut self, kind: OrderKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn order_type(mut self, order_type: OrderType) -> Self {
+        self.order_type = order_type;
+        self
+    }
+
+    /// Limit price of `Limit` and `StopLimit` orders.
+    pub fn price(mut self, price: Decimal) -> Self {
+        self.price = Some(price);
+        self
+    }
+
+    /// Trigger price of `Stop` and `StopLimit` orders.
+    pub fn trigger_price(mut self, trigger_price: Decimal) -> Self {
+        self.trigger_price = Some(trigger_price);
+        self
+    }
+
+    pub fn reduce_only(mut self, reduce_only: bool) -> Self {
+        self.reduce_only = reduce_only;
+        self
+    }
+
+    pub fn entry_timeout_tick_count(mut self, count: u32) -> Self {
+        self.entry_timeout_tick_count = count;
+        self
+    }
+
+    pub fn entry_timeout(mut self, timeout: Duration) -> Self {
+        self.entry_timeout = Some(timeout);
+        self
+    }
+
+    pub fn clock(mut self, clock: impl Into<SharedClock>) -> Self {
+        self.clock = clock.into();
+        self
+    }
+
+    pub fn build(self) -> Result<Order, OrderError> {
+        self.validate()?;
+        Ok(self.build_unchecked())
+    }
+
+    fn build_unchecked(self) -> Order {
+        Order {
+            id: self.id,
+            position_id: self.position_id,
+            side: self.side,
+            kind: self.kind,
+            order_type: self.order_type,
+            price: self.price,
+            trigger_price: self.trigger_price,
+            reduce_only: self.reduce_only,
+            amount: self.amount,
+            unfilled_amount: self.amount,
+            state: OrderState::Open,
+            fills: vec![],
+            tick_count: 0,
+            entry_timeout_tick_count: self.entry_timeout_tick_count,
+            entry_timeout_secs: self.entry_timeout.map(|d| d.num_seconds()),
+            created_timestamp: self.clock.now(),
+            clock: self.clock,
+        }
+    }
+
+    fn validate(&self) -> Result<(), OrderError> {
+        if self.amount <= Decimal::ZERO {
+            return Err(OrderError::InvalidAmount {
+                id: self.id.clone(),
+                amount: self.amount,
+            });
+        }
+        let needs_price = matches!(self.kind, OrderKind::Limit | OrderKind::StopLimit);
+        if needs_price != self.price.is_some() {
+            return Err(self.invalid_parameter("price", "must be set only for limit orders"));
+        }
+        let needs_trigger = matches!(self.kind, OrderKind::Stop | OrderKind::StopLimit);
+        if needs_trigger != self.trigger_price.is_some() {
+            return Err(self.invalid_parameter("trigger_price", "must be set only for stop orders"));
+        }
+        for (name, price) in [("price", self.price), ("trigger_price", self.trigger_price)] {
+            if price.is_some_and(|p| p <= Decimal::ZERO) {
+                return Err(self.invalid_parameter(name, "must be positive"));
+            }
+        }
+        if self.reduce_only && self.order_type == OrderType::OpenOrder {
+            return Err(self.invalid_parameter("reduce_only", "cannot open a position"));
+        }
+        if self.entry_timeout.is_some_and(|d| d.num_seconds() <= 0) {
+            return Err(self.invalid_parameter("entry_timeout", "must be positive"));
+        }
+        Ok(())
+    }
+
+    fn invalid_parameter(&self, name: &'static str, reason: &'static str) -> OrderError {
+        OrderError::InvalidParameter {
+            id: self.id.clone(),
+            name,
+            reason,
+        }
+    }
+}
+
+impl Serialize for Order {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Order::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Order {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut order = Order::deserialize(deserializer)?;
+        if order.amount.is_zero() {
+            order.amount = order.unfilled_amount;
+        }
+        Ok(order)
+    }
+}
+
+impl Order {
+    pub fn new(id: String, amount: Decimal, entry_timeout_tick_count: u32) -> Order {
+        OrderBuilder::new(&id, amount)
+            .entry_timeout_tick_count(entry_timeout_tick_count)
+            .build_unchecked()
+    }
+
+    pub fn builder(id: &str, amount: Decimal) -> OrderBuilder {
+        OrderBuilder::new(id, amount)
+    }
+
+    /// Uses `clock` for timeouts; the order is considered created now.
+    pub fn with_clock(mut self, clock: impl Into<SharedClock>) -> Self {
+        self.clock = clock.into();
+        self.created_timestamp = self.clock.now();
+        self
+    }
+
+    pub fn with_entry_timeout(mut self, timeout: Duration) -> Self {
+        self.entry_timeout_secs = Some(timeout.num_seconds());
+        self
+    }
+
+    pub fn on_filled(
+        &mut self,
+        price: Decimal,
+        amount: Decimal,
+        fee: Decimal,
+    ) -> Result<(), OrderError> {
+        self.fill(None, price, amount, fee)
+    }
+
+    /// Like `on_filled`, but rejects an execution whose `fill_id` was
+    /// already applied to this order.
+    pub fn on_filled_with_id(
+        &mut self,
+        fill_id: &str,
+        price: Decimal,
+        amount: Decimal,
+        fee: Decimal,
+    ) -> Result<(), OrderError> {
+        if self.has_processed_fill(fill_id) {
+            log::warn!(
+                "The order fill is duplicated: id = {}, fill_id = {}",
+                self.id,
+                fill_id
+            );
+            return Err(OrderError::DuplicateFill {
+                id: self.id.clone(),
+                fill_id: fill_id.to_owned(),
+            });
+        }
+        self.fill(Some(fill_id.to_owned()), price, amount, fee)
+    }
+
+    pub fn has_processed_fill(&self, fill_id: &str) -> bool {
+        self.fills
+            .iter()
+            .any(|fill| fill.fill_id.as_deref() == Some(fill_id))
+    }
+
+    fn fill(
+        &mut self,
+        fill_id: Option<String>,
+        price: Decimal,
+        amount: Decimal,
+        fee: Decimal,
+    ) -> Result<(), OrderError> {
+        if !self.state.is_working() {
+            log::warn!(
+                "The order is filled unexpectedly: id = {}, state = {}, amount = {}",
+                self.id,
+                self.state,
+                amount
+            );
+            return Err(self.invalid_state("on_filled"));
+        }
+
+        if amount <= Decimal::ZERO {
+            return Err(OrderError::InvalidAmount {
+                id: self.id.clone(),
+                amount,
+            });
+        }
+
+        if amount > self.unfilled_amount {
+            log::warn!(
+                "The order is overfilled: id = {}, unfilled_amount = {}, amount = {}",
+                self.id,
+                self.unfilled_amount,
+                amount
+            );
+            return Err(OrderError::Overfill {
+                id: self.id.clone(),
+                unfilled_amount: self.unfilled_amount,
+                filled_amount: amount,
+            });
+        }
+
+        self.unfilled_amount -= amount;
+        self.fills.push(OrderFill {
+            fill_id,
+            price,
+            amount,
+            fee,
+            timestamp: self.clock.now(),
+        });
+        self.state = if self.unfilled_amount.is_zero() {
+            OrderState::Filled
+        } else {
+            OrderState::PartiallyFilled
+        };
+
+        log::info!(
+            "Order filled: id = {}, state = {}, unfilled_amount = {}",
+            self.id,
+            self.state,
+            self.unfilled_amount
+        );
+
+        Ok(())
+    }
+
+    pub fn cancel(&mut self) -> Result<(), OrderError> {
+        if !self.state.is_working() {
+            return Err(self.invalid_state("cancel"));
+        }
+        self.finish(OrderState::Cancelled);
+        Ok(())
+    }
+
+    pub fn reject(&mut self, reason: &str) -> Result<(), OrderError> {
+        if !matches!(self.state, OrderState::Open) {
+            return Err(self.invalid_state("reject"));
+        }
+        self.finish(OrderState::Rejected(reason.to_owned()));
+        Ok(())
+    }
+
+    pub fn expire(&mut self) -> Result<(), OrderError> {
+        if !self.state.is_working() {
+            return Err(self.invalid_state("expire"));
+        }
+        self.finish(OrderState::Expired);
+        Ok(())
+    }
+
+    fn finish(&mut self, state: OrderState) {
+        self.state = state;
+        log::info!(
+            "Order finished: id = {}, state = {}, unfilled_amount = {}",
+            self.id,
+            self.state,
+            self.unfilled_amount
+        );
+    }
+
+    fn invalid_state(&self, operation: &'static str) -> OrderError {
+        OrderError::InvalidState {
+            id: self.id.clone(),
+            state: self.state.clone(),
+            operation,
+        }
+    }
+
+    pub fn should_cancel_order(&self) -> bool {
+        if self.state.is_working() {
+            self.tick_count > self.entry_timeout_tick_count
+                || self
+                    .entry_timeout_secs
+                    .is_some_and(|limit| self.clock.now() - self.created_timestamp > limit)
+        } else {
+            false
+        }
+    }
+
+    pub fn update_counter(&mut self) {
+        if self.state.is_working() {
+            self.tick_count += 1;
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn state(&self) -> OrderState {
+        self.state.clone()
+    }
+
+    pub fn position_id(&self) -> Option<u32> {
+        self.position_id
+    }
+
+    pub fn side(&self) -> PositionType {
+        self.side.clone()
+    }
+
+    pub fn kind(&self) -> OrderKind {
+        self.kind
+    }
+
+    pub fn order_type(&self) -> OrderType {
+        self.order_type
+    }
+
+    pub fn price(&self) -> Option<Decimal> {
+        self.price
+    }
+
+    pub fn trigger_price(&self) -> Option<Decimal> {
+        self.trigger_price
+    }
+
+    pub fn reduce_only(&self) -> bool {
+        self.reduce_only
+    }
+
+    /// Unfilled amount signed by side (long positive), zero once the order
+    /// stopped working.
+    pub fn pending_exposure(&self) -> Decimal {
+        if !self.state.is_working() {
+            return Decimal::ZERO;
+        }
+        match self.side {
+            PositionType::Long => self.unfilled_amount,
+            PositionType::Short => -self.unfilled_amount,
+        }
+    }
+
+    pub fn amount(&self) -> Decimal {
+        self.amount
+    }
+
+    pub fn unfilled_amount(&self) -> Decimal {
+        self.unfilled_amount
+    }
+
+    pub fn filled_amount(&self) -> Decimal {
+        self.amount - self.unfilled_amount
+    }
+
+    pub fn fills(&self) -> &[OrderFill] {
+        &self.fills
+    }
+
+    pub fn fee(&self) -> Decimal {
+        self.fills.iter().map(|fill| fill.fee).sum()
+    }
+
+    pub fn average_fill_price(&self) -> Option<Decimal> {
+        let filled_amount = self.filled_amount();
+        if filled_amount.is_zero() {
+            return None;
+        }
+        let notional: Decimal = self.fills.iter().map(|fill| fill.price * fill.amount).sum();
+        Some(notional / filled_amount)
+    }
+
+    pub fn entry_timeout(&self) -> Option<Duration> {
+        self.entry_timeout_secs.map(Duration::seconds)
+    }
+
+    pub fn created_timestamp(&self) -> i64 {
+        self.created_timestamp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn legacy_order_takes_its_amount_from_the_unfilled_amount() {
+        let order: Order = serde_json::from_str(
+            r#"{"id":"o","unfilled_amount":"2","state":"Open","tick_count":0,"entry_timeout_tick_count":3}"#,
+        )
+        .unwrap();
+        assert_eq!(order.amount(), dec!(2));
+        assert_eq!(order.filled_amount(), dec!(0));
+        assert_eq!(order.average_fill_price(), None);
+    }
+
+    #[test]
+    fn partially_filled_order_survives_serialization() {
+        let mut order = Order::builder("o", dec!(2)).build().unwrap();
+        order.on_filled(dec!(100), dec!(0.5), dec!(0.1)).unwrap();
+
+        let order: Order = serde_json::from_str(&serde_json::to_string(&order).unwrap()).unwrap();
+        assert_eq!(order.amount(), dec!(2));
+        assert_eq!(order.filled_amount(), dec!(0.5));
+        assert_eq!(order.average_fill_price(), Some(dec!(100)));
+    }
+}
@@ -0,0 +1,411 @@
+use crate::{
+    EntryTimeoutAction, ExitSignal, Fill, FillOutcome, Position, PositionBuilder, PositionError,
+    PositionState, PositionType, ReasonForClose, SharedClock,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PositionAction {
+    Close {
+        id: u32,
+        reason: ReasonForClose,
+    },
+    Reduce {
+        id: u32,
+        amount: Decimal,
+        level: usize,
+    },
+    CancelClosing {
+        id: u32,
+    },
+    EntryTimeout {
+        id: u32,
+        action: EntryTimeoutAction,
+    },
+    Expire {
+        id: u32,
+    },
+}
+
+/// Owns every position of a bot, allocates their ids and drives them
+/// tick by tick.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PositionBook {
+    next_id: u32,
+    positions: BTreeMap<u32, Position>,
+    #[serde(skip)]
+    clock: SharedClock,
+}
+
+impl PositionBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_first_id(first_id: u32) -> Self {
+        Self {
+            next_id: first_id,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_clock(mut self, clock: impl Into<SharedClock>) -> Self {
+        self.clock = clock.into();
+        self
+    }
+
+    /// Replaces the clock of the book and of every position it owns, e.g.
+    /// after deserialization.
+    pub fn set_clock(&mut self, clock: impl Into<SharedClock>) {
+        self.clock = clock.into();
+        for position in self.positions.values_mut() {
+            position.set_clock(self.clock.clone());
+        }
+    }
+
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    /// Returns a builder carrying a freshly allocated id and the book's clock.
+    pub fn builder(&mut self, fund_name: &str, token_name: &str) -> PositionBuilder {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        PositionBuilder::new(id, fund_name, token_name).clock(self.clock.clone())
+    }
+
+    pub fn insert(&mut self, position: Position) -> Result<u32, PositionError> {
+        let id = position.id();
+        if self.positions.contains_key(&id) {
+            return Err(PositionError::DuplicatePosition { id });
+        }
+        if id >= self.next_id {
+            self.next_id = id.wrapping_add(1);
+        }
+        self.positions.insert(id, position);
+        Ok(id)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Position> {
+        self.positions.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Position> {
+        self.positions.get_mut(&id)
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<Position> {
+        self.positions.remove(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Position> {
+        self.positions.values()
+    }
+
+    pub fn positions_for<'a>(
+        &'a self,
+        fund_name: &'a str,
+        token_name: &'a str,
+    ) -> impl Iterator<Item = &'a Position> + 'a {
+        self.positions
+            .values()
+            .filter(move |p| p.fund_name() == fund_name && p.token_name() == token_name)
+    }
+
+    /// Removes and returns every closed position.
+    pub fn drain_closed(&mut self) -> Vec<Position> {
+        let ids: Vec<u32> = self
+            .positions
+            .values()
+            .filter(|p| matches!(p.state(), PositionState::Closed(_)))
+            .map(|p| p.id())
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| self.positions.remove(&id))
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn on_filled(
+        &mut self,
+        id: u32,
+        position_type: PositionType,
+        filled_price: Decimal,
+        amount: Decimal,
+        asset_in_usd: Decimal,
+        fee: Decimal,
+        take_profit_price: Option<Decimal>,
+        cut_loss_price: Option<Decimal>,
+        current_price: Decimal,
+    ) -> Result<(), PositionError> {
+        self.position_mut(id)?.on_filled(
+            position_type,
+            filled_price,
+            amount,
+            asset_in_usd,
+            fee,
+            take_profit_price,
+            cut_loss_price,
+            current_price,
+        )
+    }
+
+    /// Applies `fill` to a position. A position split by an inversion is
+    /// added to the book under a new id.
+    pub fn apply_fill(&mut self, id: u32, fill: Fill) -> Result<FillOutcome, PositionError> {
+        match self.position_mut(id)?.apply_fill(fill)? {
+            FillOutcome::Split(mut residual) => {
+                let new_id = self.next_id;
+                self.next_id = self.next_id.wrapping_add(1);
+                residual.set_id(new_id);
+                self.insert((*residual).clone())?;
+                Ok(FillOutcome::Split(residual))
+            }
+            outcome => Ok(outcome),
+        }
+    }
+
+    pub fn on_liquidated(
+        &mut self,
+        id: u32,
+        close_price: Decimal,
+        fee: Decimal,
+        do_liquidate: bool,
+        liquidated_reason: Option<String>,
+    ) -> Result<(), PositionError> {
+        self.position_mut(id)?
+            .on_liquidated(close_price, fee, do_liquidate, liquidated_reason)
+    }
+
+    pub fn bust_fill(&mut self, id: u32, fill_id: &str) -> Result<(), PositionError> {
+        self.position_mut(id)?.bust_fill(fill_id)
+    }
+
+    pub fn request_close(
+        &mut self,
+        id: u32,
+        reason: impl Into<ReasonForClose>,
+    ) -> Result<(), PositionError> {
+        self.position_mut(id)?.request_close(reason)
+    }
+
+    pub fn request_reduce(
+        &mut self,
+        id: u32,
+        amount: Decimal,
+        reason: impl Into<ReasonForClose>,
+    ) -> Result<(), PositionError> {
+        self.position_mut(id)?.request_reduce(amount, reason)
+    }
+
+    pub fn cancel_reduce(&mut self, id: u32) -> Result<(), PositionError> {
+        self.position_mut(id)?.cancel_reduce()
+    }
+
+    pub fn cancel_closing(&mut self, id: u32) -> Result<(), PositionError> {
+        self.position_mut(id)?.cancel_closing()
+    }
+
+    /// Advances every live position by one tick and returns what the caller
+    /// should do. Positions whose token has no price are only counted.
+    pub fn tick<F>(&mut self, price_of: F, use_trailing: bool) -> Vec<PositionAction>
+    where
+        F: Fn(&str) -> Option<Decimal>,
+    {
+        let mut actions = vec![];
+        let now = self.clock.now();
+
+        for position in self.positions.values_mut() {
+            if matches!(position.state(), PositionState::Closed(_)) {
+                continue;
+            }
+
+            position.update_counter();
+            let id = position.id();
+
+            if position.should_cancel_closing() {
+                actions.push(PositionAction::CancelClosing { id });
+                continue;
+            }
+
+            if let Some(action) = position.entry_timeout_action() {
+                actions.push(PositionAction::EntryTimeout { id, action });
+            }
+
+            let Some(price) = price_of(position.token_name()) else {
+                continue;
+            };
+            position.on_price(price, now);
+
+            match position.should_close(price, use_trailing) {
+                Some(ExitSignal::Close(reason)) => {
+                    actions.push(PositionAction::Close { id, reason })
+                }
+                Some(ExitSignal::Reduce { amount, level }) => {
+                    actions.push(PositionAction::Reduce { id, amount, level })
+                }
+                None if position.should_open_expired(price) => {
+                    actions.push(PositionAction::Expire { id })
+                }
+                None => {}
+            }
+        }
+
+        actions
+    }
+
+    fn position_mut(&mut self, id: u32) -> Result<&mut Position, PositionError> {
+        self.positions
+            .get_mut(&id)
+            .ok_or(PositionError::UnknownPosition { id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InversionPolicy, ManualClock};
+    use rust_decimal_macros::dec;
+    use std::sync::Arc;
+
+    fn fill(position_type: PositionType, price: Decimal, amount: Decimal) -> Fill {
+        Fill {
+            position_type,
+            price,
+            amount,
+            asset_in_usd: price * amount,
+            current_price: price,
+            ..Fill::default()
+        }
+    }
+
+    fn entry(position_type: PositionType, take_profit: Decimal, cut_loss: Decimal) -> Fill {
+        Fill {
+            take_profit_price: Some(take_profit),
+            cut_loss_price: Some(cut_loss),
+            ..fill(position_type, dec!(100), dec!(1))
+        }
+    }
+
+    #[test]
+    fn ids_are_allocated_after_the_highest_inserted_one() {
+        let mut book = PositionBook::with_first_id(5);
+        let first = book.builder("fund", "BTC").build().unwrap();
+        assert_eq!(book.insert(first).unwrap(), 5);
+
+        let external = PositionBuilder::new(10, "fund", "BTC").build().unwrap();
+        assert_eq!(book.insert(external.clone()).unwrap(), 10);
+        assert!(matches!(
+            book.insert(external),
+            Err(PositionError::DuplicatePosition { id: 10 })
+        ));
+
+        let next = book.builder("fund", "ETH").build().unwrap();
+        assert_eq!(next.id(), 11);
+        assert!(matches!(
+            book.apply_fill(42, Fill::default()),
+            Err(PositionError::UnknownPosition { id: 42 })
+        ));
+    }
+
+    #[test]
+    fn tick_returns_the_action_of_each_live_position() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut book = PositionBook::new().with_clock(clock.clone());
+        let open = |book: &mut PositionBook, token: &str, fill: Fill| {
+            let position = book.builder("fund", token).build().unwrap();
+            let id = book.insert(position).unwrap();
+            book.apply_fill(id, fill).unwrap();
+            id
+        };
+
+        let take_profit = open(
+            &mut book,
+            "BTC",
+            entry(PositionType::Long, dec!(110), dec!(90)),
+        );
+        let cut_loss = open(
+            &mut book,
+            "ETH",
+            entry(PositionType::Short, dec!(90), dec!(110)),
+        );
+        let unpriced = open(
+            &mut book,
+            "SOL",
+            entry(PositionType::Long, dec!(110), dec!(90)),
+        );
+        let closed = open(
+            &mut book,
+            "BTC",
+            entry(PositionType::Long, dec!(110), dec!(90)),
+        );
+        book.request_close(closed, ReasonForClose::Manual).unwrap();
+        book.apply_fill(closed, fill(PositionType::Short, dec!(100), dec!(1)))
+            .unwrap();
+
+        let actions = book.tick(
+            |token| match token {
+                "BTC" => Some(dec!(111)),
+                "ETH" => Some(dec!(111)),
+                _ => None,
+            },
+            false,
+        );
+
+        assert_eq!(
+            actions,
+            vec![
+                PositionAction::Close {
+                    id: take_profit,
+                    reason: ReasonForClose::TakeProfit
+                },
+                PositionAction::Close {
+                    id: cut_loss,
+                    reason: ReasonForClose::CutLoss
+                },
+            ]
+        );
+        assert_eq!(book.get(unpriced).unwrap().last_price(), None);
+        assert_eq!(book.drain_closed().len(), 1);
+        assert!(book.get(closed).is_none());
+    }
+
+    #[test]
+    fn split_position_is_added_under_a_new_id() {
+        let mut book = PositionBook::new();
+        let position = book
+            .builder("fund", "BTC")
+            .inversion_policy(InversionPolicy::Split)
+            .build()
+            .unwrap();
+        let id = book.insert(position).unwrap();
+        book.apply_fill(id, fill(PositionType::Long, dec!(100), dec!(1)))
+            .unwrap();
+
+        let outcome = book
+            .apply_fill(id, fill(PositionType::Short, dec!(99), dec!(2)))
+            .unwrap();
+        let FillOutcome::Split(residual) = outcome else {
+            panic!("not split: {:?}", outcome);
+        };
+
+        assert_ne!(residual.id(), id);
+        let stored = book.get(residual.id()).unwrap();
+        assert_eq!(stored.amount(), dec!(-1));
+        assert_eq!(
+            book.get(id).unwrap().state(),
+            PositionState::Closed(ReasonForClose::Inverted)
+        );
+        assert_eq!(book.len(), 2);
+    }
+}
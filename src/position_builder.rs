@@ -0,0 +1,418 @@
+use crate::{
+    clock::default_time_zone, BracketMergePolicy, CostBasisMethod, IndicatorSnapshot,
+    InversionPolicy, Position, PositionError, PositionType, SharedClock, StopRule, TakeProfitLevel,
+    TrailingStop, VolatilityStop,
+};
+use chrono::{Duration, FixedOffset};
+use debot_db::CandlePattern;
+use rust_decimal::Decimal;
+
+type Series = (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal);
+type PatternSeries = (
+    CandlePattern,
+    CandlePattern,
+    CandlePattern,
+    CandlePattern,
+    CandlePattern,
+    CandlePattern,
+);
+
+/// Named-parameter constructor for [`Position`].
+///
+/// Only the id, fund and token are required. Tick limits default to
+/// `u32::MAX` (no limit) and every other parameter to zero / `None`, so a
+/// position can be limited by wall-clock durations instead of ticks.
+#[derive(Clone, Debug)]
+pub struct PositionBuilder {
+    pub(crate) id: u32,
+    pub(crate) fund_name: String,
+    pub(crate) token_name: String,
+    pub(crate) position_type: PositionType,
+    pub(crate) target_price: Decimal,
+    pub(crate) exit_timeout_tick_count: u32,
+    pub(crate) max_holding_tick_count: u32,
+    pub(crate) max_holding_duration: Option<Duration>,
+    pub(crate) exit_timeout: Option<Duration>,
+    pub(crate) entry_amount: Option<Decimal>,
+    pub(crate) min_entry_fraction: Decimal,
+    pub(crate) entry_timeout_tick_count: u32,
+    pub(crate) entry_timeout: Option<Duration>,
+    pub(crate) inversion_policy: InversionPolicy,
+    pub(crate) buffer_early_fills: bool,
+    pub(crate) cost_basis_method: CostBasisMethod,
+    pub(crate) take_profit_merge_policy: BracketMergePolicy,
+    pub(crate) cut_loss_merge_policy: BracketMergePolicy,
+    pub(crate) indicators: IndicatorSnapshot,
+    pub(crate) take_profit_ratio: Decimal,
+    pub(crate) atr_spread: Decimal,
+    pub(crate) risk_reward: Decimal,
+    pub(crate) atr_term: Decimal,
+    pub(crate) tick_spread: i64,
+    pub(crate) bias_ticks: i64,
+    pub(crate) last_volume: Option<Decimal>,
+    pub(crate) last_num_trades: Option<u64>,
+    pub(crate) last_funding_rate: Option<Decimal>,
+    pub(crate) last_open_interest: Option<Decimal>,
+    pub(crate) last_oracle_price: Option<Decimal>,
+    pub(crate) clock: SharedClock,
+    pub(crate) time_zone: FixedOffset,
+    pub(crate) trailing_stop: TrailingStop,
+    pub(crate) volatility_stops: Vec<VolatilityStop>,
+    pub(crate) stop_rules: Vec<StopRule>,
+    pub(crate) take_profit_levels: Vec<TakeProfitLevel>,
+}
+
+impl PositionBuilder {
+    pub fn new(id: u32, fund_name: &str, token_name: &str) -> Self {
+        Self {
+            id,
+            fund_name: fund_name.to_owned(),
+            token_name: token_name.to_owned(),
+            position_type: PositionType::default(),
+            target_price: Decimal::ZERO,
+            exit_timeout_tick_count: u32::MAX,
+            max_holding_tick_count: u32::MAX,
+            max_holding_duration: None,
+            exit_timeout: None,
+            entry_amount: None,
+            min_entry_fraction: Decimal::ZERO,
+            entry_timeout_tick_count: u32::MAX,
+            entry_timeout: None,
+            inversion_policy: InversionPolicy::default(),
+            buffer_early_fills: false,
+            cost_basis_method: CostBasisMethod::default(),
+            take_profit_merge_policy: BracketMergePolicy::default(),
+            cut_loss_merge_policy: BracketMergePolicy::default(),
+            indicators: IndicatorSnapshot::default(),
+            take_profit_ratio: Decimal::ZERO,
+            atr_spread: Decimal::ZERO,
+            risk_reward: Decimal::ZERO,
+            atr_term: Decimal::ZERO,
+            tick_spread: 0,
+            bias_ticks: 0,
+            last_volume: None,
+            last_num_trades: None,
+            last_funding_rate: None,
+            last_open_interest: None,
+            last_oracle_price: None,
+            clock: SharedClock::default(),
+            time_zone: default_time_zone(),
+            trailing_stop: TrailingStop::default(),
+            volatility_stops: vec![],
+            stop_rules: vec![],
+            take_profit_levels: vec![],
+        }
+    }
+
+    pub fn position_type(mut self, position_type: PositionType) -> Self {
+        self.position_type = position_type;
+        self
+    }
+
+    pub fn target_price(mut self, target_price: Decimal) -> Self {
+        self.target_price = target_price;
+        self
+    }
+
+    pub fn exit_timeout_tick_count(mut self, count: u32) -> Self {
+        self.exit_timeout_tick_count = count;
+        self
+    }
+
+    pub fn max_holding_tick_count(mut self, count: u32) -> Self {
+        self.max_holding_tick_count = count;
+        self
+    }
+
+    /// How long an open position may be held before `should_open_expired`
+    /// reports it. Applies alongside `max_holding_tick_count`.
+    pub fn max_holding_duration(mut self, duration: Duration) -> Self {
+        self.max_holding_duration = Some(duration);
+        self
+    }
+
+    /// How long a close may stay pending before `should_cancel_closing`
+    /// reports it. Applies alongside `exit_timeout_tick_count`.
+    pub fn exit_timeout(mut self, duration: Duration) -> Self {
+        self.exit_timeout = Some(duration);
+        self
+    }
+
+    /// Target entry size. Until it is filled the position is `PartiallyOpen`.
+    pub fn entry_amount(mut self, amount: Decimal) -> Self {
+        self.entry_amount = Some(amount);
+        self
+    }
+
+    /// Share of `entry_amount` a timed out entry must have filled to be
+    /// kept rather than unwound.
+    pub fn min_entry_fraction(mut self, fraction: Decimal) -> Self {
+        self.min_entry_fraction = fraction;
+        self
+    }
+
+    pub fn entry_timeout_tick_count(mut self, count: u32) -> Self {
+        self.entry_timeout_tick_count = count;
+        self
+    }
+
+    /// How long the entry may stay incomplete before
+    /// `entry_timeout_action` reports it. Applies alongside
+    /// `entry_timeout_tick_count`.
+    pub fn entry_timeout(mut self, duration: Duration) -> Self {
+        self.entry_timeout = Some(duration);
+        self
+    }
+
+    pub fn inversion_policy(mut self, policy: InversionPolicy) -> Self {
+        self.inversion_policy = policy;
+        self
+    }
+
+    /// Buffers a fill opposite to `position_type` that arrives before the
+    /// entry even while the position is `Ready`. Without it, such a fill is
+    /// buffered only once the entry is started and otherwise opens the
+    /// position on its own side.
+    pub fn buffer_early_fills(mut self, buffer: bool) -> Self {
+        self.buffer_early_fills = buffer;
+        self
+    }
+
+    pub fn cost_basis_method(mut self, method: CostBasisMethod) -> Self {
+        self.cost_basis_method = method;
+        self
+    }
+
+    /// How the take-profit price of an increasing fill is merged with the
+    /// current one.
+    pub fn take_profit_merge_policy(mut self, policy: BracketMergePolicy) -> Self {
+        self.take_profit_merge_policy = policy;
+        self
+    }
+
+    /// How the cut-loss price of an increasing fill is merged with the
+    /// current one.
+    pub fn cut_loss_merge_policy(mut self, policy: BracketMergePolicy) -> Self {
+        self.cut_loss_merge_policy = policy;
+        self
+    }
+
+    pub fn atr(mut self, atr: Series) -> Self {
+        self.indicators.atr = atr.into();
+        self
+    }
+
+    pub fn adx(mut self, adx: Series) -> Self {
+        self.indicators.adx = adx.into();
+        self
+    }
+
+    pub fn rsi(mut self, rsi: Series) -> Self {
+        self.indicators.rsi = rsi.into();
+        self
+    }
+
+    pub fn stochastic(mut self, stochastic: Series) -> Self {
+        self.indicators.stochastic = stochastic.into();
+        self
+    }
+
+    pub fn price(mut self, price: Series) -> Self {
+        self.indicators.price = price.into();
+        self
+    }
+
+    pub fn candle_pattern(mut self, candle_pattern: PatternSeries) -> Self {
+        self.indicators.candle_pattern = candle_pattern.into();
+        self
+    }
+
+    pub fn indicators(mut self, indicators: IndicatorSnapshot) -> Self {
+        self.indicators = indicators;
+        self
+    }
+
+    pub fn take_profit_ratio(mut self, take_profit_ratio: Decimal) -> Self {
+        self.take_profit_ratio = take_profit_ratio;
+        self
+    }
+
+    pub fn atr_spread(mut self, atr_spread: Decimal) -> Self {
+        self.atr_spread = atr_spread;
+        self
+    }
+
+    pub fn risk_reward(mut self, risk_reward: Decimal) -> Self {
+        self.risk_reward = risk_reward;
+        self
+    }
+
+    pub fn atr_term(mut self, atr_term: Decimal) -> Self {
+        self.atr_term = atr_term;
+        self
+    }
+
+    pub fn tick_spread(mut self, tick_spread: i64) -> Self {
+        self.tick_spread = tick_spread;
+        self
+    }
+
+    pub fn bias_ticks(mut self, bias_ticks: i64) -> Self {
+        self.bias_ticks = bias_ticks;
+        self
+    }
+
+    pub fn last_volume(mut self, last_volume: Option<Decimal>) -> Self {
+        self.last_volume = last_volume;
+        self
+    }
+
+    pub fn last_num_trades(mut self, last_num_trades: Option<u64>) -> Self {
+        self.last_num_trades = last_num_trades;
+        self
+    }
+
+    pub fn last_funding_rate(mut self, last_funding_rate: Option<Decimal>) -> Self {
+        self.last_funding_rate = last_funding_rate;
+        self
+    }
+
+    pub fn last_open_interest(mut self, last_open_interest: Option<Decimal>) -> Self {
+        self.last_open_interest = last_open_interest;
+        self
+    }
+
+    pub fn last_oracle_price(mut self, last_oracle_price: Option<Decimal>) -> Self {
+        self.last_oracle_price = last_oracle_price;
+        self
+    }
+
+    pub fn clock(mut self, clock: impl Into<SharedClock>) -> Self {
+        self.clock = clock.into();
+        self
+    }
+
+    /// Time zone of `open_time_str` / `close_time_str`. Defaults to the
+    /// `TIMEZONE_OFFSET` environment variable (seconds east of UTC).
+    pub fn time_zone(mut self, time_zone: FixedOffset) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    /// Trailing stop used by `should_close(.., use_trailing = true)`.
+    /// Defaults to half of the expected profit, activated at take-profit.
+    pub fn trailing_stop(mut self, trailing_stop: TrailingStop) -> Self {
+        self.trailing_stop = trailing_stop;
+        self
+    }
+
+    pub fn volatility_stop(mut self, stop: VolatilityStop) -> Self {
+        self.volatility_stops.push(stop);
+        self
+    }
+
+    pub fn stop_rule(mut self, rule: StopRule) -> Self {
+        self.stop_rules.push(rule);
+        self
+    }
+
+    /// Adds a level to the take-profit ladder. Levels are reported by
+    /// `should_close` in the order they are added.
+    pub fn take_profit_level(mut self, level: TakeProfitLevel) -> Self {
+        self.take_profit_levels.push(level);
+        self
+    }
+
+    pub fn build(self) -> Result<Position, PositionError> {
+        self.validate()?;
+        Ok(self.build_unchecked())
+    }
+
+    pub(crate) fn build_unchecked(self) -> Position {
+        Position::from_builder(self)
+    }
+
+    fn validate(&self) -> Result<(), PositionError> {
+        if self.fund_name.is_empty() {
+            return Err(self.invalid_parameter("fund_name", "must not be empty"));
+        }
+        if self.token_name.is_empty() {
+            return Err(self.invalid_parameter("token_name", "must not be empty"));
+        }
+        if self.target_price.is_sign_negative() {
+            return Err(self.invalid_parameter("target_price", "must not be negative"));
+        }
+        if self.exit_timeout_tick_count == 0 {
+            return Err(self.invalid_parameter("exit_timeout_tick_count", "must be positive"));
+        }
+        if self.max_holding_tick_count == 0 {
+            return Err(self.invalid_parameter("max_holding_tick_count", "must be positive"));
+        }
+        if self.entry_timeout_tick_count == 0 {
+            return Err(self.invalid_parameter("entry_timeout_tick_count", "must be positive"));
+        }
+        if self
+            .entry_amount
+            .is_some_and(|amount| amount <= Decimal::ZERO)
+        {
+            return Err(self.invalid_parameter("entry_amount", "must be positive"));
+        }
+        if self.min_entry_fraction.is_sign_negative() || self.min_entry_fraction > Decimal::ONE {
+            return Err(self.invalid_parameter("min_entry_fraction", "must be in [0, 1]"));
+        }
+        for (name, duration) in [
+            ("max_holding_duration", self.max_holding_duration),
+            ("exit_timeout", self.exit_timeout),
+            ("entry_timeout", self.entry_timeout),
+        ] {
+            if duration.is_some_and(|d| d.num_seconds() <= 0) {
+                return Err(self.invalid_parameter(name, "must be positive"));
+            }
+        }
+        for (name, value) in [
+            ("take_profit_ratio", self.take_profit_ratio),
+            ("atr_spread", self.atr_spread),
+            ("risk_reward", self.risk_reward),
+            ("atr_term", self.atr_term),
+        ] {
+            if value.is_sign_negative() {
+                return Err(self.invalid_parameter(name, "must not be negative"));
+            }
+        }
+        if !self.trailing_stop.is_valid() {
+            return Err(self.invalid_parameter("trailing_stop", "must have positive parameters"));
+        }
+        if !self.volatility_stops.iter().all(|stop| stop.is_valid()) {
+            return Err(self.invalid_parameter("volatility_stop", "must have valid parameters"));
+        }
+        if !self.stop_rules.iter().all(|rule| rule.is_valid()) {
+            return Err(self.invalid_parameter("stop_rule", "must have valid parameters"));
+        }
+        if !self.take_profit_levels.iter().all(|level| level.is_valid()) {
+            return Err(self.invalid_parameter(
+                "take_profit_level",
+                "must have a positive price and a fraction in (0, 1]",
+            ));
+        }
+        let total_fraction: Decimal = self
+            .take_profit_levels
+            .iter()
+            .map(|level| level.fraction)
+            .sum();
+        if total_fraction > Decimal::ONE {
+            return Err(self.invalid_parameter("take_profit_level", "fractions must not exceed 1"));
+        }
+        if self.tick_spread < 0 {
+            return Err(self.invalid_parameter("tick_spread", "must not be negative"));
+        }
+        Ok(())
+    }
+
+    fn invalid_parameter(&self, name: &'static str, reason: &'static str) -> PositionError {
+        PositionError::InvalidParameter {
+            id: self.id,
+            name,
+            reason,
+        }
+    }
+}
@@ -1,16 +1,34 @@
-use crate::PositionType;
+use crate::{
+    bracket::BracketKind,
+    clock::{default_time_zone, format_time},
+    lot,
+    trailing_stop::TrailingContext,
+    volatility_stop, BracketMergePolicy, CostBasisMethod, Fill, FillOutcome, IndicatorSnapshot,
+    Lot, ManualClock, Order, OrderType, PositionBuilder, PositionError, PositionEvent,
+    PositionType, SharedClock, StopModification, StopRule, TakeProfitTarget, TrailingActivation,
+    TrailingStop, VolatilityStop,
+};
+use chrono::{DateTime, Duration, FixedOffset, Offset, Utc};
 use debot_db::CandlePattern;
-use debot_utils::get_local_time;
 use rust_decimal::{prelude::Signed, Decimal};
 use serde::{Deserialize, Serialize};
-use std::{cell::RefCell, fmt};
+use std::{collections::BTreeSet, fmt, sync::Arc};
 
-#[derive(Debug, Clone, PartialEq)]
+/// Serialized as its `Display` name, the format of the reasons stored as
+/// plain strings before 4.0.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "String", into = "String")]
 pub enum ReasonForClose {
     Liquidated,
     Expired,
     TakeProfit,
     CutLoss,
+    Manual,
+    RiskLimit,
+    TrailingStop,
+    Signal,
+    Funding,
+    Inverted,
     Other(String),
 }
 
@@ -21,32 +39,158 @@ impl fmt::Display for ReasonForClose {
             ReasonForClose::Expired => write!(f, "Expired"),
             ReasonForClose::TakeProfit => write!(f, "TakeProfit"),
             ReasonForClose::CutLoss => write!(f, "CutLoss"),
+            ReasonForClose::Manual => write!(f, "Manual"),
+            ReasonForClose::RiskLimit => write!(f, "RiskLimit"),
+            ReasonForClose::TrailingStop => write!(f, "TrailingStop"),
+            ReasonForClose::Signal => write!(f, "Signal"),
+            ReasonForClose::Funding => write!(f, "Funding"),
+            ReasonForClose::Inverted => write!(f, "Inverted"),
             ReasonForClose::Other(s) => write!(f, "{}", s),
         }
     }
 }
 
+/// Parses the names printed by `Display`; anything else becomes `Other`.
+impl From<&str> for ReasonForClose {
+    fn from(reason: &str) -> Self {
+        match reason {
+            "Liquidated" => ReasonForClose::Liquidated,
+            "Expired" => ReasonForClose::Expired,
+            "TakeProfit" => ReasonForClose::TakeProfit,
+            "CutLoss" => ReasonForClose::CutLoss,
+            "Manual" => ReasonForClose::Manual,
+            "RiskLimit" => ReasonForClose::RiskLimit,
+            "TrailingStop" => ReasonForClose::TrailingStop,
+            "Signal" => ReasonForClose::Signal,
+            "Funding" => ReasonForClose::Funding,
+            "Inverted" => ReasonForClose::Inverted,
+            other => ReasonForClose::Other(other.to_owned()),
+        }
+    }
+}
+
+impl From<String> for ReasonForClose {
+    fn from(reason: String) -> Self {
+        reason.as_str().into()
+    }
+}
+
+impl From<ReasonForClose> for String {
+    fn from(reason: ReasonForClose) -> Self {
+        reason.to_string()
+    }
+}
+
+/// What `should_close` asks the caller to do with the position.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExitSignal {
+    Close(ReasonForClose),
+    /// Reduce the position by `amount` for the take-profit level at index
+    /// `level` of the ladder.
+    Reduce {
+        amount: Decimal,
+        level: usize,
+    },
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
 pub enum PositionState {
     #[default]
     Ready,
+    /// Entry order working, nothing filled yet.
+    Opening,
+    /// Entry partly filled, short of the target entry amount.
+    PartiallyOpen,
     Open,
-    Closing(String),
-    Closed(String),
+    /// Open with part of the amount requested to be closed.
+    Reducing(ReasonForClose),
+    Closing(ReasonForClose),
+    Closed(ReasonForClose),
 }
 
 impl fmt::Display for PositionState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             PositionState::Ready => write!(f, "Ready"),
+            PositionState::Opening => write!(f, "Opening"),
+            PositionState::PartiallyOpen => write!(f, "PartiallyOpen"),
             PositionState::Open => write!(f, "Open"),
+            PositionState::Reducing(reason) => write!(f, "Reducing({})", reason),
             PositionState::Closing(reason) => write!(f, "Closing({})", reason),
             PositionState::Closed(reason) => write!(f, "Closed({})", reason),
         }
     }
 }
 
+impl PositionState {
+    /// Transition table of the position lifecycle.
+    pub fn can_transition_to(&self, next: &PositionState) -> bool {
+        use PositionState::*;
+        matches!(
+            (self, next),
+            (Ready, Opening)
+                | (Ready, PartiallyOpen)
+                | (Ready, Open)
+                | (Opening, PartiallyOpen)
+                | (Opening, Open)
+                | (Opening, Closed(_))
+                | (PartiallyOpen, Open)
+                | (PartiallyOpen, Closing(_))
+                | (PartiallyOpen, Closed(_))
+                | (Open, Reducing(_))
+                | (Open, Closing(_))
+                | (Open, Closed(_))
+                | (Reducing(_), Open)
+                | (Reducing(_), Closing(_))
+                | (Reducing(_), Closed(_))
+                | (Closing(_), Open)
+                | (Closing(_), Closed(_))
+        )
+    }
+}
+
+/// What happens when an opposite fill is larger than the position.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InversionPolicy {
+    /// The position turns to the other side and keeps its history.
+    #[default]
+    Invert,
+    /// The position is closed with `ReasonForClose::Inverted` and the
+    /// residual amount opens a new position, returned in
+    /// `FillOutcome::Split`.
+    ///
+    /// The new position carries the id of the closed one: the caller must
+    /// give it its own with `Position::set_id` before storing it.
+    /// `PositionBook::apply_fill` does so.
+    Split,
+}
+
+/// What to do with an entry that did not complete in time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryTimeoutAction {
+    /// Nothing was filled: cancel the entry order, then `cancel_entry`.
+    CancelEntry,
+    /// Cancel the rest of the entry order and keep the partial position
+    /// with `finish_entry`.
+    KeepPartial { unfilled_amount: Decimal },
+    /// Cancel the rest of the entry order and close the partial position.
+    UnwindPartial {
+        filled_amount: Decimal,
+        unfilled_amount: Decimal,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StateTransition {
+    pub from: PositionState,
+    pub to: PositionState,
+    pub timestamp: i64,
+}
+
+/// Fields missing from data serialized by an older version take their
+/// default value.
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct Position {
     id: u32,
     fund_name: String,
@@ -57,9 +201,28 @@ pub struct Position {
     actual_hold_tick: u32,
     max_holding_tick_count: u32,
     exit_timeout_tick_count: u32,
-    open_time_str: String,
+    entry_timeout_tick_count: u32,
+    max_holding_secs: Option<i64>,
+    exit_timeout_secs: Option<i64>,
+    entry_timeout_secs: Option<i64>,
+    entry_amount: Option<Decimal>,
+    min_entry_fraction: Decimal,
+    entry_timestamp: i64,
+    inversion_policy: InversionPolicy,
+    buffer_early_fills: bool,
+    cost_basis_method: CostBasisMethod,
+    lots: Vec<Lot>,
+    take_profit_merge_policy: BracketMergePolicy,
+    cut_loss_merge_policy: BracketMergePolicy,
     open_timestamp: i64,
-    close_time_str: String,
+    closing_timestamp: i64,
+    close_timestamp: i64,
+    /// Close time of a position serialized by 3.x, which had no
+    /// `close_timestamp`.
+    #[serde(rename = "close_time_str", skip_serializing_if = "String::is_empty")]
+    legacy_close_time_str: String,
+    #[serde(default = "default_utc_offset_seconds")]
+    utc_offset_seconds: i32,
     average_open_price: Decimal,
     position_type: PositionType,
     target_price: Decimal,
@@ -69,23 +232,30 @@ pub struct Position {
     close_asset_in_usd: Decimal,
     amount: Decimal,
     asset_in_usd: Decimal,
+    entry_position_type: PositionType,
     pnl: Decimal,
     fee: Decimal,
-    trailing_peak_price: RefCell<Option<Decimal>>,
+    trailing_stop: TrailingStop,
+    trailing_peak_price: Option<Decimal>,
+    volatility_stops: Vec<VolatilityStop>,
+    current_atr: Option<Decimal>,
+    atr_trailing_stop_price: Option<Decimal>,
+    stop_rules: Vec<StopRule>,
+    stop_history: Vec<StopModification>,
+    take_profit_targets: Vec<TakeProfitTarget>,
+    pending_reduce_amount: Decimal,
+    state_history: Vec<StateTransition>,
+    /// Cause reported by the exchange with a liquidation.
+    liquidation_reason: Option<String>,
+    last_price: Option<Decimal>,
+    last_price_timestamp: i64,
+    high_water_mark: Option<Decimal>,
+    low_water_mark: Option<Decimal>,
+    max_favorable_excursion: Decimal,
+    max_adverse_excursion: Decimal,
     // for debug
-    atr: (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal),
-    adx: (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal),
-    rsi: (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal),
-    stochastic: (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal),
-    price: (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal),
-    candle_pattern: (
-        CandlePattern,
-        CandlePattern,
-        CandlePattern,
-        CandlePattern,
-        CandlePattern,
-        CandlePattern,
-    ),
+    entry_indicators: IndicatorSnapshot,
+    exit_indicators: Option<IndicatorSnapshot>,
     take_profit_ratio: Decimal,
     atr_spread: Decimal,
     risk_reward: Decimal,
@@ -97,31 +267,31 @@ pub struct Position {
     last_funding_rate: Option<Decimal>,
     last_open_interest: Option<Decimal>,
     last_oracle_price: Option<Decimal>,
+    orders: Vec<Order>,
+    journal: Vec<PositionEvent>,
+    /// Whether the last journal event is a price that changed nothing.
+    quiet_price_recorded: bool,
+    processed_fill_ids: BTreeSet<String>,
+    busted_fill_ids: BTreeSet<String>,
+    pending_fills: Vec<Fill>,
+    #[serde(skip)]
+    clock: SharedClock,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
-pub enum OrderState {
-    #[default]
-    Open,
-    Filled,
-}
-
-impl fmt::Display for OrderState {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            OrderState::Open => write!(f, "Open"),
-            OrderState::Filled => write!(f, "Filled"),
-        }
-    }
+fn default_utc_offset_seconds() -> i32 {
+    default_time_zone().local_minus_utc()
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
-pub struct Order {
-    id: String,
-    unfilled_amount: Decimal,
-    state: OrderState,
-    tick_count: u32,
-    entry_timeout_tick_count: u32,
+#[derive(PartialEq)]
+struct PriceMarks {
+    high_water_mark: Option<Decimal>,
+    low_water_mark: Option<Decimal>,
+    max_favorable_excursion: Decimal,
+    max_adverse_excursion: Decimal,
+    trailing_peak_price: Option<Decimal>,
+    atr_trailing_stop_price: Option<Decimal>,
+    cut_loss_price: Option<Decimal>,
+    take_profit_hits: usize,
 }
 
 enum UpdateResult {
@@ -130,12 +300,8 @@ enum UpdateResult {
     Inverted,
 }
 
-pub enum OrderType {
-    OpenOrder,
-    CloseOrder,
-}
-
 impl Position {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: u32,
         fund_name: &str,
@@ -169,23 +335,69 @@ impl Position {
         last_open_interest: Option<Decimal>,
         last_oracle_price: Option<Decimal>,
     ) -> Self {
+        PositionBuilder::new(id, fund_name, token_name)
+            .exit_timeout_tick_count(exit_timeout_tick_count)
+            .max_holding_tick_count(max_holding_tick_count)
+            .position_type(position_type)
+            .target_price(target_price)
+            .atr(atr)
+            .adx(adx)
+            .rsi(rsi)
+            .stochastic(stochastic)
+            .price(price)
+            .candle_pattern(candle_pattern)
+            .take_profit_ratio(take_profit_ratio)
+            .atr_spread(atr_spread)
+            .risk_reward(risk_reward)
+            .atr_term(atr_term)
+            .tick_spread(tick_spread)
+            .bias_ticks(bias_ticks)
+            .last_volume(last_volume)
+            .last_num_trades(last_num_trades)
+            .last_funding_rate(last_funding_rate)
+            .last_open_interest(last_open_interest)
+            .last_oracle_price(last_oracle_price)
+            .build_unchecked()
+    }
+
+    pub fn builder(id: u32, fund_name: &str, token_name: &str) -> PositionBuilder {
+        PositionBuilder::new(id, fund_name, token_name)
+    }
+
+    pub(crate) fn from_builder(builder: PositionBuilder) -> Self {
         let decimal_0 = Decimal::new(0, 0);
         Self {
-            id,
-            fund_name: fund_name.to_owned(),
+            id: builder.id,
+            fund_name: builder.fund_name,
             tick_count: 0,
             actual_entry_tick: 0,
             actual_hold_tick: 0,
-            max_holding_tick_count,
-            exit_timeout_tick_count,
+            max_holding_tick_count: builder.max_holding_tick_count,
+            exit_timeout_tick_count: builder.exit_timeout_tick_count,
+            max_holding_secs: builder.max_holding_duration.map(|d| d.num_seconds()),
+            exit_timeout_secs: builder.exit_timeout.map(|d| d.num_seconds()),
+            entry_timeout_tick_count: builder.entry_timeout_tick_count,
+            entry_timeout_secs: builder.entry_timeout.map(|d| d.num_seconds()),
+            entry_amount: builder.entry_amount,
+            min_entry_fraction: builder.min_entry_fraction,
+            entry_timestamp: 0,
+            inversion_policy: builder.inversion_policy,
+            buffer_early_fills: builder.buffer_early_fills,
+            cost_basis_method: builder.cost_basis_method,
+            lots: vec![],
+            take_profit_merge_policy: builder.take_profit_merge_policy,
+            cut_loss_merge_policy: builder.cut_loss_merge_policy,
             state: PositionState::Ready,
-            token_name: token_name.to_owned(),
-            open_time_str: String::new(),
+            token_name: builder.token_name,
             open_timestamp: 0,
-            close_time_str: String::new(),
+            closing_timestamp: 0,
+            close_timestamp: 0,
+            legacy_close_time_str: String::new(),
+            utc_offset_seconds: builder.time_zone.local_minus_utc(),
             average_open_price: decimal_0,
-            position_type,
-            target_price,
+            entry_position_type: builder.position_type.clone(),
+            position_type: builder.position_type,
+            target_price: builder.target_price,
             take_profit_price: None,
             cut_loss_price: None,
             close_price: decimal_0,
@@ -194,27 +406,360 @@ impl Position {
             asset_in_usd: decimal_0,
             pnl: decimal_0,
             fee: decimal_0,
-            trailing_peak_price: None.into(),
-            atr,
-            adx,
-            rsi,
-            price,
-            candle_pattern,
-            take_profit_ratio,
-            stochastic,
-            atr_spread,
-            risk_reward,
-            atr_term,
-            tick_spread,
-            bias_ticks,
-            last_volume,
-            last_num_trades,
-            last_funding_rate,
-            last_open_interest,
-            last_oracle_price,
+            trailing_stop: builder.trailing_stop,
+            trailing_peak_price: None,
+            volatility_stops: builder.volatility_stops,
+            current_atr: None,
+            atr_trailing_stop_price: None,
+            stop_rules: builder.stop_rules,
+            stop_history: vec![],
+            take_profit_targets: builder
+                .take_profit_levels
+                .into_iter()
+                .map(TakeProfitTarget::new)
+                .collect(),
+            pending_reduce_amount: decimal_0,
+            state_history: vec![],
+            liquidation_reason: None,
+            last_price: None,
+            last_price_timestamp: 0,
+            high_water_mark: None,
+            low_water_mark: None,
+            max_favorable_excursion: decimal_0,
+            max_adverse_excursion: decimal_0,
+            entry_indicators: builder.indicators,
+            exit_indicators: None,
+            take_profit_ratio: builder.take_profit_ratio,
+            atr_spread: builder.atr_spread,
+            risk_reward: builder.risk_reward,
+            atr_term: builder.atr_term,
+            tick_spread: builder.tick_spread,
+            bias_ticks: builder.bias_ticks,
+            last_volume: builder.last_volume,
+            last_num_trades: builder.last_num_trades,
+            last_funding_rate: builder.last_funding_rate,
+            last_open_interest: builder.last_open_interest,
+            last_oracle_price: builder.last_oracle_price,
+            orders: vec![],
+            journal: vec![],
+            quiet_price_recorded: false,
+            processed_fill_ids: BTreeSet::new(),
+            busted_fill_ids: BTreeSet::new(),
+            pending_fills: vec![],
+            clock: builder.clock,
+        }
+    }
+
+    /// Builder carrying the configuration this position was created with.
+    pub fn to_builder(&self) -> PositionBuilder {
+        PositionBuilder {
+            id: self.id,
+            fund_name: self.fund_name.clone(),
+            token_name: self.token_name.clone(),
+            position_type: self.entry_position_type.clone(),
+            target_price: self.target_price,
+            exit_timeout_tick_count: self.exit_timeout_tick_count,
+            max_holding_tick_count: self.max_holding_tick_count,
+            max_holding_duration: self.max_holding_duration(),
+            exit_timeout: self.exit_timeout(),
+            entry_amount: self.entry_amount,
+            min_entry_fraction: self.min_entry_fraction,
+            entry_timeout_tick_count: self.entry_timeout_tick_count,
+            entry_timeout: self.entry_timeout(),
+            inversion_policy: self.inversion_policy,
+            buffer_early_fills: self.buffer_early_fills,
+            cost_basis_method: self.cost_basis_method,
+            take_profit_merge_policy: self.take_profit_merge_policy,
+            cut_loss_merge_policy: self.cut_loss_merge_policy,
+            indicators: self.entry_indicators,
+            take_profit_ratio: self.take_profit_ratio,
+            atr_spread: self.atr_spread,
+            risk_reward: self.risk_reward,
+            atr_term: self.atr_term,
+            tick_spread: self.tick_spread,
+            bias_ticks: self.bias_ticks,
+            last_volume: self.last_volume,
+            last_num_trades: self.last_num_trades,
+            last_funding_rate: self.last_funding_rate,
+            last_open_interest: self.last_open_interest,
+            last_oracle_price: self.last_oracle_price,
+            clock: self.clock.clone(),
+            time_zone: self.time_zone(),
+            trailing_stop: self.trailing_stop.clone(),
+            volatility_stops: self.volatility_stops.clone(),
+            stop_rules: self.stop_rules.clone(),
+            take_profit_levels: self
+                .take_profit_targets
+                .iter()
+                .map(|target| target.level.clone())
+                .collect(),
+        }
+    }
+
+    /// Rebuilds a position by applying `events` to a fresh position built
+    /// from `builder`, which must be the configuration the position was
+    /// built with. Event timestamps are used as the clock while replaying.
+    pub fn replay(
+        builder: PositionBuilder,
+        events: &[PositionEvent],
+    ) -> Result<Position, PositionError> {
+        let replay_clock = Arc::new(ManualClock::default());
+        let mut position = builder.build_unchecked();
+        let clock = std::mem::replace(&mut position.clock, replay_clock.clone().into());
+
+        let busted_fill_ids: BTreeSet<&str> = events
+            .iter()
+            .filter_map(|event| match event {
+                PositionEvent::FillBusted { fill_id, .. } => Some(fill_id.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        for event in events {
+            replay_clock.set(event.timestamp());
+            match event {
+                PositionEvent::Filled { fill, .. }
+                    if fill
+                        .fill_id
+                        .as_deref()
+                        .is_some_and(|id| busted_fill_ids.contains(id)) =>
+                {
+                    // Kept for the audit trail only
+                    position.processed_fill_ids.extend(fill.fill_id.clone());
+                    position.record(event.clone());
+                }
+                PositionEvent::FillBusted { fill_id, .. } => {
+                    position.busted_fill_ids.insert(fill_id.clone());
+                    position.record(event.clone());
+                }
+                _ => position.apply_event(event.clone())?,
+            }
+        }
+
+        position.clock = clock;
+        Ok(position)
+    }
+
+    /// Configuration the position was built with: the current one with the
+    /// journaled configuration changes undone.
+    fn initial_builder(&self) -> PositionBuilder {
+        let mut builder = self.to_builder();
+        for event in self.journal.iter().rev() {
+            match event {
+                PositionEvent::StopRuleAdded { .. } => {
+                    builder.stop_rules.pop();
+                }
+                PositionEvent::VolatilityStopAdded { .. } => {
+                    builder.volatility_stops.pop();
+                }
+                PositionEvent::TrailingStopChanged { previous, .. } => {
+                    builder.trailing_stop = previous.clone();
+                }
+                PositionEvent::TimeZoneChanged { previous, .. } => {
+                    builder.time_zone =
+                        FixedOffset::east_opt(*previous).unwrap_or_else(|| Utc.fix());
+                }
+                _ => {}
+            }
+        }
+        builder
+    }
+
+    /// Replays `events` on a fresh copy of this position, keeping what the
+    /// journal does not cover (orders, exit indicators, buffered fills).
+    fn rebuild(&self, events: &[PositionEvent]) -> Result<Position, PositionError> {
+        let mut position = Self::replay(self.initial_builder(), events)?;
+        position.orders = self.orders.clone();
+        position.exit_indicators = self.exit_indicators;
+        position
+            .pending_fills
+            .extend(self.pending_fills.iter().cloned());
+        Ok(position)
+    }
+
+    pub fn apply_event(&mut self, event: PositionEvent) -> Result<(), PositionError> {
+        match event {
+            PositionEvent::Filled { fill, .. } => self.apply_fill(fill).map(|_| ()),
+            PositionEvent::FillBusted { fill_id, .. } => self.bust_fill(&fill_id),
+            PositionEvent::CloseRequested { reason, .. } => self.request_close(reason),
+            PositionEvent::ClosingCancelled { .. } => self.cancel_closing(),
+            PositionEvent::EntryStarted { .. } => self.start_entry(),
+            PositionEvent::EntryFinished { .. } => self.finish_entry(),
+            PositionEvent::EntryCancelled { .. } => self.cancel_entry(),
+            PositionEvent::ReduceRequested { amount, reason, .. } => {
+                self.request_reduce(amount, reason)
+            }
+            PositionEvent::ReduceCancelled { .. } => self.cancel_reduce(),
+            PositionEvent::Liquidated {
+                close_price,
+                fee,
+                do_liquidate,
+                reason,
+                ..
+            } => self.on_liquidated(close_price, fee, do_liquidate, reason),
+            PositionEvent::Tick { count, .. } => {
+                for _ in 0..count {
+                    self.update_counter();
+                }
+                Ok(())
+            }
+            PositionEvent::PriceUpdated { price, timestamp } => {
+                self.on_price(price, timestamp);
+                Ok(())
+            }
+            PositionEvent::AtrUpdated { atr, .. } => {
+                self.update_atr(atr);
+                Ok(())
+            }
+            PositionEvent::StopRuleAdded { rule, .. } => {
+                self.add_stop_rule(rule);
+                Ok(())
+            }
+            PositionEvent::VolatilityStopAdded { stop, .. } => {
+                self.add_volatility_stop(stop);
+                Ok(())
+            }
+            PositionEvent::TrailingStopChanged { trailing_stop, .. } => {
+                self.set_trailing_stop(trailing_stop);
+                Ok(())
+            }
+            PositionEvent::TimeZoneChanged {
+                utc_offset_seconds, ..
+            } => {
+                let time_zone =
+                    FixedOffset::east_opt(utc_offset_seconds).unwrap_or_else(|| Utc.fix());
+                self.set_time_zone(time_zone);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn has_processed_fill(&self, fill_id: &str) -> bool {
+        self.processed_fill_ids.contains(fill_id)
+    }
+
+    /// Fills held back because they arrived before the entry was filled.
+    pub fn pending_fills(&self) -> &[Fill] {
+        &self.pending_fills
+    }
+
+    /// Reverses a previously applied fill, recomputing the position from the
+    /// remaining fills. The fill id stays known so a re-delivery is rejected.
+    pub fn bust_fill(&mut self, fill_id: &str) -> Result<(), PositionError> {
+        if !self.processed_fill_ids.contains(fill_id) || self.busted_fill_ids.contains(fill_id) {
+            return Err(PositionError::UnknownFill {
+                id: self.id,
+                fill_id: fill_id.to_owned(),
+            });
+        }
+
+        let mut events = self.journal.clone();
+        events.push(PositionEvent::FillBusted {
+            fill_id: fill_id.to_owned(),
+            timestamp: self.clock.now(),
+        });
+        *self = self.rebuild(&events)?;
+
+        log::warn!(
+            "Fill busted[{}][{}]: {}",
+            self.id,
+            fill_id,
+            self.format_position(self.average_open_price)
+        );
+
+        Ok(())
+    }
+
+    fn last_applied_fill(&self) -> Option<&Fill> {
+        self.journal.iter().rev().find_map(|event| match event {
+            PositionEvent::Filled { fill, .. }
+                if !fill
+                    .fill_id
+                    .as_ref()
+                    .is_some_and(|id| self.busted_fill_ids.contains(id)) =>
+            {
+                Some(fill)
+            }
+            _ => None,
+        })
+    }
+
+    /// Inserts a late fill before the first applied fill that the exchange
+    /// executed after it, and recomputes the position.
+    fn apply_late_fill(&mut self, fill: Fill) -> Result<(), PositionError> {
+        let index = self
+            .journal
+            .iter()
+            .position(|event| match event {
+                PositionEvent::Filled { fill: applied, .. } => fill.is_before(applied),
+                _ => false,
+            })
+            .unwrap_or(self.journal.len());
+        let timestamp = self
+            .journal
+            .get(index)
+            .map(|event| event.timestamp())
+            .unwrap_or_else(|| self.clock.now());
+
+        let mut events = self.journal.clone();
+        events.insert(index, PositionEvent::Filled { fill, timestamp });
+        *self = self.rebuild(&events)?;
+
+        log::warn!(
+            "Late fill reordered[{}]: {}",
+            self.id,
+            self.format_position(self.average_open_price)
+        );
+
+        Ok(())
+    }
+
+    pub fn events(&self) -> &[PositionEvent] {
+        &self.journal
+    }
+
+    fn record(&mut self, event: PositionEvent) {
+        self.quiet_price_recorded = false;
+        self.journal.push(event);
+    }
+
+    /// Ticks in a row are kept as one event. A quiet price update at the end
+    /// of the journal does not interrupt them, as the two commute.
+    fn record_tick(&mut self, timestamp: i64) {
+        let end = self.journal.len() - usize::from(self.quiet_price_recorded);
+        if let Some(PositionEvent::Tick {
+            count,
+            timestamp: last_timestamp,
+        }) = end.checked_sub(1).and_then(|i| self.journal.get_mut(i))
+        {
+            *count += 1;
+            *last_timestamp = timestamp;
+            return;
         }
+        self.journal.insert(
+            end,
+            PositionEvent::Tick {
+                count: 1,
+                timestamp,
+            },
+        );
     }
 
+    /// A price that changed nothing but the last price replaces the previous
+    /// such price instead of growing the journal.
+    fn record_price(&mut self, price: Decimal, timestamp: i64, quiet: bool) {
+        let event = PositionEvent::PriceUpdated { price, timestamp };
+        if quiet && self.quiet_price_recorded {
+            if let Some(last) = self.journal.last_mut() {
+                *last = event;
+            }
+            return;
+        }
+        self.record(event);
+        self.quiet_price_recorded = quiet;
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn on_filled(
         &mut self,
         position_type: PositionType,
@@ -225,43 +770,223 @@ impl Position {
         take_profit_price: Option<Decimal>,
         cut_loss_price: Option<Decimal>,
         current_price: Decimal,
-    ) -> Result<(), ()> {
+    ) -> Result<(), PositionError> {
+        self.apply_fill(Fill {
+            fill_id: None,
+            position_type,
+            price: filled_price,
+            amount,
+            asset_in_usd,
+            fee,
+            take_profit_price,
+            cut_loss_price,
+            current_price,
+            sequence: None,
+            timestamp: None,
+        })
+        .map(|_| ())
+    }
+
+    /// Applies an exchange fill. While the entry is working (`Opening`), a
+    /// fill opposite to the entry side is buffered until the entry fills. In
+    /// `Ready` the first fill sets the side, unless the position was built
+    /// with `buffer_early_fills`. A fill executed before the last applied one
+    /// is put back in sequence.
+    ///
+    /// Under `InversionPolicy::Split` the position returned in
+    /// `FillOutcome::Split` still has the id of this one; assign it a new id
+    /// with `set_id`.
+    pub fn apply_fill(&mut self, fill: Fill) -> Result<FillOutcome, PositionError> {
+        if let Some(fill_id) = &fill.fill_id {
+            let buffered = self
+                .pending_fills
+                .iter()
+                .any(|pending| pending.fill_id.as_ref() == Some(fill_id));
+            if buffered || self.processed_fill_ids.contains(fill_id) {
+                log::warn!("on_filled: duplicate fill[{}][{}]", self.id, fill_id);
+                return Err(PositionError::DuplicateFill {
+                    id: self.id,
+                    fill_id: fill_id.clone(),
+                });
+            }
+        }
+
+        if fill.amount <= Decimal::ZERO {
+            return Err(PositionError::InvalidAmount {
+                id: self.id,
+                amount: fill.amount,
+            });
+        }
+
+        if self
+            .last_applied_fill()
+            .is_some_and(|last| fill.is_before(last))
+        {
+            self.apply_late_fill(fill)?;
+            return Ok(FillOutcome::Reordered);
+        }
+
         if matches!(self.state, PositionState::Closed(_)) {
-            log::error!("on_filled: Invalid position state: {:?}", self);
-            return Err(());
+            return Err(self.invalid_state("on_filled"));
         }
 
-        log::trace!("state = {}, amount = {}", self.state, amount);
+        let entry_side_known = match self.state {
+            PositionState::Opening => true,
+            PositionState::Ready => self.buffer_early_fills,
+            _ => false,
+        };
+        if entry_side_known && fill.position_type != self.position_type {
+            log::warn!(
+                "on_filled: buffer a {} fill before the entry[{}]",
+                fill.position_type,
+                self.id
+            );
+            self.pending_fills.push(fill);
+            return Ok(FillOutcome::Buffered);
+        }
 
-        self.fee += fee;
+        if let Some(fill_id) = &fill.fill_id {
+            self.processed_fill_ids.insert(fill_id.clone());
+        }
+
+        log::trace!("state = {}, amount = {}", self.state, fill.amount);
 
-        if self.state == PositionState::Ready {
-            self.position_type = position_type.clone();
+        self.record(PositionEvent::Filled {
+            fill: fill.clone(),
+            timestamp: self.clock.now(),
+        });
+
+        if self.inversion_policy == InversionPolicy::Split
+            && !self.is_awaiting_entry()
+            && fill.position_type != self.position_type
+            && fill.amount > self.amount.abs()
+        {
+            return self.split_inversion(fill);
+        }
+
+        self.fee += fill.fee;
+
+        if self.is_awaiting_entry() {
+            self.position_type = fill.position_type.clone();
         }
 
-        if self.position_type == position_type {
+        if self.position_type == fill.position_type {
             self.increase(
-                position_type,
-                filled_price,
-                take_profit_price,
-                cut_loss_price,
-                amount,
-                asset_in_usd,
-                current_price,
-            );
+                fill.position_type,
+                fill.price,
+                fill.take_profit_price,
+                fill.cut_loss_price,
+                fill.amount,
+                fill.asset_in_usd,
+                fill.current_price,
+            )?;
         } else {
             self.decrease(
-                position_type,
-                filled_price,
-                take_profit_price,
-                cut_loss_price,
-                amount,
-                asset_in_usd,
-                current_price,
-            );
+                fill.position_type,
+                fill.price,
+                fill.take_profit_price,
+                fill.cut_loss_price,
+                fill.amount,
+                fill.asset_in_usd,
+                fill.current_price,
+                None,
+            )?;
         }
 
-        return Ok(());
+        if !self.is_awaiting_entry() && !self.pending_fills.is_empty() {
+            self.apply_pending_fills();
+        }
+
+        Ok(FillOutcome::Applied)
+    }
+
+    /// Closes the position with the part of `fill` it covers and opens a
+    /// new position with the same configuration for the rest. The new
+    /// position has no entry indicators, and no take-profit ladder or
+    /// activation price, as those were set for the other side.
+    fn split_inversion(&mut self, fill: Fill) -> Result<FillOutcome, PositionError> {
+        let closing_amount = self.amount.abs();
+        let ratio = closing_amount / fill.amount;
+        let closing_asset_in_usd = fill.asset_in_usd * ratio;
+        let closing_fee = fill.fee * ratio;
+
+        self.fee += closing_fee;
+        self.decrease(
+            fill.position_type.clone(),
+            fill.price,
+            None,
+            None,
+            closing_amount,
+            closing_asset_in_usd,
+            fill.current_price,
+            Some(ReasonForClose::Inverted),
+        )?;
+
+        let mut builder = self.to_builder().position_type(fill.position_type.clone());
+        builder.entry_amount = None;
+        builder.indicators = IndicatorSnapshot::default();
+        builder.take_profit_levels.clear();
+        builder.trailing_stop.activation = TrailingActivation::default();
+        let mut residual = builder.build_unchecked();
+        residual.apply_fill(Fill {
+            amount: fill.amount - closing_amount,
+            asset_in_usd: fill.asset_in_usd - closing_asset_in_usd,
+            fee: fill.fee - closing_fee,
+            ..fill
+        })?;
+
+        log::info!(
+            "- The position[{}] is split: {}",
+            self.id,
+            residual.format_position(residual.average_open_price)
+        );
+
+        Ok(FillOutcome::Split(Box::new(residual)))
+    }
+
+    pub fn inversion_policy(&self) -> InversionPolicy {
+        self.inversion_policy
+    }
+
+    pub fn take_profit_merge_policy(&self) -> BracketMergePolicy {
+        self.take_profit_merge_policy
+    }
+
+    pub fn cut_loss_merge_policy(&self) -> BracketMergePolicy {
+        self.cut_loss_merge_policy
+    }
+
+    pub fn cost_basis_method(&self) -> CostBasisMethod {
+        self.cost_basis_method
+    }
+
+    /// Every lot opened by the position, oldest first, including closed ones.
+    pub fn lots(&self) -> &[Lot] {
+        &self.lots
+    }
+
+    pub fn open_lots(&self) -> impl Iterator<Item = &Lot> {
+        self.lots.iter().filter(|lot| lot.is_open())
+    }
+
+    /// Assigns the id of a position split off by an inversion, which is
+    /// returned with the id of the position it came from.
+    /// `PositionBook::apply_fill` does it with an id of the book.
+    pub fn set_id(&mut self, id: u32) {
+        self.id = id;
+    }
+
+    /// Applies the buffered fills once the entry is filled. A buffered fill
+    /// that fails is dropped and logged; it does not affect the others nor
+    /// the fill that completed the entry.
+    fn apply_pending_fills(&mut self) {
+        let mut pending = std::mem::take(&mut self.pending_fills);
+        pending.sort_by_key(|fill| (fill.sequence, fill.timestamp));
+        for fill in pending {
+            if let Err(e) = self.apply_fill(fill) {
+                log::warn!("on_filled: drop a buffered fill[{}]: {}", self.id, e);
+            }
+        }
     }
 
     pub fn on_liquidated(
@@ -270,40 +995,213 @@ impl Position {
         fee: Decimal,
         do_liquidate: bool,
         liquidated_reason: Option<String>,
-    ) -> Result<(), ()> {
-        self.fee += fee;
-
+    ) -> Result<(), PositionError> {
         let reason = if do_liquidate {
-            match liquidated_reason {
-                Some(r) => format!("Liquidated, {}", r),
-                None => String::from("Liquidated"),
-            }
+            ReasonForClose::Liquidated
         } else {
             match self.state.clone() {
                 PositionState::Closing(reason) => reason,
-                _ => {
-                    log::error!("delete: Invalid PositionState: {}", self.state);
-                    return Err(());
-                }
+                _ => return Err(self.invalid_state("on_liquidated")),
             }
         };
+        if !self.can_close_with(&reason) {
+            return Err(self.invalid_state("on_liquidated"));
+        }
+
+        if do_liquidate {
+            if let Some(r) = &liquidated_reason {
+                log::info!("The position[{}] is liquidated: {}", self.id, r);
+            }
+            self.liquidation_reason = liquidated_reason.clone();
+        }
+        self.record(PositionEvent::Liquidated {
+            close_price,
+            fee,
+            do_liquidate,
+            reason: liquidated_reason,
+            timestamp: self.clock.now(),
+        });
+
+        self.fee += fee;
+        self.delete(close_price, reason)
+    }
+
+    pub fn request_close(
+        &mut self,
+        reason: impl Into<ReasonForClose>,
+    ) -> Result<(), PositionError> {
+        if !self.is_holding() {
+            return Err(self.invalid_state("request_close"));
+        }
 
-        self.delete(close_price, &reason);
+        let reason = reason.into();
+        self.record(PositionEvent::CloseRequested {
+            reason: reason.clone(),
+            timestamp: self.clock.now(),
+        });
 
-        return Ok(());
+        self.pending_reduce_amount = Decimal::ZERO;
+        self.update_state(PositionState::Closing(reason))
     }
 
-    pub fn request_close(&mut self, reason: &str) -> Result<(), ()> {
+    /// Asks to close `amount` of the position. The position stays
+    /// `Reducing` until opposite fills cover the amount.
+    pub fn request_reduce(
+        &mut self,
+        amount: Decimal,
+        reason: impl Into<ReasonForClose>,
+    ) -> Result<(), PositionError> {
         if !matches!(self.state, PositionState::Open) {
-            log::error!("request_close: Invalid position state: {:?}", self);
-            return Err(());
+            return Err(self.invalid_state("request_reduce"));
+        }
+        if amount <= Decimal::ZERO || amount > self.amount.abs() {
+            return Err(PositionError::InvalidAmount {
+                id: self.id,
+                amount,
+            });
         }
 
-        self.update_state(PositionState::Closing(reason.to_owned()));
+        let reason = reason.into();
+        self.record(PositionEvent::ReduceRequested {
+            amount,
+            reason: reason.clone(),
+            timestamp: self.clock.now(),
+        });
+
+        self.pending_reduce_amount = amount;
+        self.update_state(PositionState::Reducing(reason))
+    }
+
+    pub fn cancel_reduce(&mut self) -> Result<(), PositionError> {
+        if !matches!(self.state, PositionState::Reducing(_)) {
+            return Err(self.invalid_state("cancel_reduce"));
+        }
+
+        self.record(PositionEvent::ReduceCancelled {
+            timestamp: self.clock.now(),
+        });
+
+        self.pending_reduce_amount = Decimal::ZERO;
+        self.update_state(PositionState::Open)
+    }
+
+    /// Amount still to be closed for the last `request_reduce`.
+    pub fn pending_reduce_amount(&self) -> Decimal {
+        self.pending_reduce_amount
+    }
+
+    fn settle_reduce(&mut self, amount: Decimal) -> Result<(), PositionError> {
+        if !matches!(self.state, PositionState::Reducing(_)) {
+            return Ok(());
+        }
+
+        self.pending_reduce_amount = (self.pending_reduce_amount - amount).max(Decimal::ZERO);
+        if self.pending_reduce_amount.is_zero() {
+            log::info!("The reduce of the position[{}] is done", self.id);
+            self.update_state(PositionState::Open)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the position holds an amount that is not being fully closed.
+    fn is_holding(&self) -> bool {
+        matches!(
+            self.state,
+            PositionState::PartiallyOpen | PositionState::Open | PositionState::Reducing(_)
+        )
+    }
+
+    fn is_awaiting_entry(&self) -> bool {
+        matches!(self.state, PositionState::Ready | PositionState::Opening)
+    }
+
+    /// Marks the entry order as working.
+    pub fn start_entry(&mut self) -> Result<(), PositionError> {
+        if self.state != PositionState::Ready {
+            return Err(self.invalid_state("start_entry"));
+        }
+
+        self.record(PositionEvent::EntryStarted {
+            timestamp: self.clock.now(),
+        });
+        self.update_state(PositionState::Opening)
+    }
+
+    /// Accepts a partial entry as the whole position, e.g. after the rest
+    /// of the entry order was cancelled.
+    pub fn finish_entry(&mut self) -> Result<(), PositionError> {
+        if self.state != PositionState::PartiallyOpen {
+            return Err(self.invalid_state("finish_entry"));
+        }
 
-        return Ok(());
+        self.record(PositionEvent::EntryFinished {
+            timestamp: self.clock.now(),
+        });
+        self.update_state(PositionState::Open)
     }
 
+    /// Gives up an entry that was never filled.
+    pub fn cancel_entry(&mut self) -> Result<(), PositionError> {
+        if self.state != PositionState::Opening {
+            return Err(self.invalid_state("cancel_entry"));
+        }
+
+        self.record(PositionEvent::EntryCancelled {
+            timestamp: self.clock.now(),
+        });
+        self.update_state(PositionState::Closed(ReasonForClose::Expired))
+    }
+
+    pub fn entry_amount(&self) -> Option<Decimal> {
+        self.entry_amount
+    }
+
+    pub fn entry_timeout(&self) -> Option<Duration> {
+        self.entry_timeout_secs.map(Duration::seconds)
+    }
+
+    /// What to do once the entry has been working longer than the entry
+    /// timeout, or `None` while it may still complete.
+    pub fn entry_timeout_action(&self) -> Option<EntryTimeoutAction> {
+        if !matches!(
+            self.state,
+            PositionState::Opening | PositionState::PartiallyOpen
+        ) {
+            return None;
+        }
+        if self.tick_count <= self.entry_timeout_tick_count
+            && !self.has_elapsed(self.entry_timestamp, self.entry_timeout_secs)
+        {
+            return None;
+        }
+
+        if self.state == PositionState::Opening {
+            return Some(EntryTimeoutAction::CancelEntry);
+        }
+
+        let filled_amount = self.amount.abs();
+        let entry_amount = self.entry_amount.unwrap_or(filled_amount);
+        let unfilled_amount = (entry_amount - filled_amount).max(Decimal::ZERO);
+        if filled_amount >= entry_amount * self.min_entry_fraction {
+            Some(EntryTimeoutAction::KeepPartial { unfilled_amount })
+        } else {
+            Some(EntryTimeoutAction::UnwindPartial {
+                filled_amount,
+                unfilled_amount,
+            })
+        }
+    }
+
+    fn invalid_state(&self, operation: &'static str) -> PositionError {
+        log::error!("{}: Invalid position state: {:?}", operation, self);
+        PositionError::InvalidState {
+            id: self.id,
+            state: self.state.clone(),
+            operation,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn increase(
         &mut self,
         position_type: PositionType,
@@ -313,44 +1211,59 @@ impl Position {
         amount: Decimal,
         asset_in_usd: Decimal,
         current_price: Decimal,
-    ) {
+    ) -> Result<(), PositionError> {
         let current_amount = self.amount.abs();
 
         self.average_open_price = (self.average_open_price * current_amount
             + filled_price * amount)
             / (current_amount + amount);
 
-        self.take_profit_price = match take_profit_price {
-            Some(new_price) => match self.take_profit_price {
-                Some(current_price) => Some(
-                    (current_price * current_amount + new_price * amount)
-                        / (current_amount + amount),
-                ),
-                None => Some(new_price),
-            },
-            None => None,
-        };
-
-        self.cut_loss_price = match cut_loss_price {
-            Some(new_price) => match self.cut_loss_price {
-                Some(current_price) => Some(
-                    (current_price * current_amount + new_price * amount)
-                        / (current_amount + amount),
-                ),
-                None => Some(new_price),
-            },
-            None => None,
-        };
+        self.take_profit_price = self.take_profit_merge_policy.merge(
+            BracketKind::TakeProfit,
+            &position_type,
+            self.take_profit_price,
+            current_amount,
+            take_profit_price,
+            amount,
+        );
+        self.cut_loss_price = self.cut_loss_merge_policy.merge(
+            BracketKind::CutLoss,
+            &position_type,
+            self.cut_loss_price,
+            current_amount,
+            cut_loss_price,
+            amount,
+        );
 
+        self.lots.push(Lot::new(
+            position_type.clone(),
+            filled_price,
+            amount,
+            self.clock.now(),
+        ));
         self.update_amount(position_type, amount, asset_in_usd);
-        self.update_state(PositionState::Open);
+        if self.is_awaiting_entry() || self.state == PositionState::PartiallyOpen {
+            let entered = self
+                .entry_amount
+                .is_none_or(|entry_amount| self.amount.abs() >= entry_amount);
+            let next_state = if entered {
+                PositionState::Open
+            } else {
+                PositionState::PartiallyOpen
+            };
+            if next_state != self.state {
+                self.update_state(next_state)?;
+            }
+        }
 
         log::info!(
             "+ Increase the position: {}",
             self.format_position(current_price)
         );
+        Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn decrease(
         &mut self,
         position_type: PositionType,
@@ -360,47 +1273,94 @@ impl Position {
         amount: Decimal,
         asset_in_usd: Decimal,
         current_price: Decimal,
-    ) {
+        close_reason: Option<ReasonForClose>,
+    ) -> Result<(), PositionError> {
         self.close_asset_in_usd += asset_in_usd;
 
         match self.update_amount_and_pnl(position_type, amount, asset_in_usd, filled_price) {
             UpdateResult::Closed => {
-                let reason = if self.pnl > Decimal::ZERO {
-                    "TakeProfit"
-                } else {
-                    "CutLoss"
-                };
-                self.delete(filled_price, reason);
-                return;
+                self.attribute_reduction(amount);
+                self.pending_reduce_amount = Decimal::ZERO;
+                let reason = close_reason.unwrap_or_else(|| self.infer_close_reason(filled_price));
+                self.delete(filled_price, reason)?;
             }
             UpdateResult::Inverted => {
+                self.reset_price_marks();
+                self.settle_reduce(self.pending_reduce_amount)?;
                 self.average_open_price = filled_price;
                 self.take_profit_price = take_profit_price;
                 self.cut_loss_price = cut_loss_price;
                 self.position_type = self.position_type.opposite();
+                self.lots.push(Lot::new(
+                    self.position_type.clone(),
+                    filled_price,
+                    self.amount.abs(),
+                    self.clock.now(),
+                ));
                 log::info!(
                     "- The position is inverted: {}",
                     self.format_position(filled_price)
                 );
             }
             UpdateResult::Decreased => {
+                self.attribute_reduction(amount);
+                self.settle_reduce(amount)?;
                 log::info!(
                     "** The position is decreased: {}",
                     self.format_position(current_price)
                 );
             }
         }
+        Ok(())
+    }
+
+    /// Reason for a close nobody requested, from the exit level reached by
+    /// the closing price. Falls back on the sign of the realized PnL.
+    fn infer_close_reason(&self, close_price: Decimal) -> ReasonForClose {
+        if self.should_take_profit(close_price, false) {
+            ReasonForClose::TakeProfit
+        } else if self.is_trailing_stop_triggered(close_price) {
+            ReasonForClose::TrailingStop
+        } else if self.should_cut_loss(close_price) {
+            ReasonForClose::CutLoss
+        } else if self.should_volatility_stop(close_price) {
+            ReasonForClose::TrailingStop
+        } else if self.pnl > Decimal::ZERO {
+            ReasonForClose::TakeProfit
+        } else {
+            ReasonForClose::CutLoss
+        }
     }
 
-    fn delete(&mut self, close_price: Decimal, reason: &str) {
-        if let PositionState::Closing(closing_reason) = self.state.clone() {
-            self.update_state(PositionState::Closed(closing_reason));
-        } else {
-            self.update_state(PositionState::Closed(reason.to_owned()));
+    /// State `delete` moves the position to.
+    fn closed_state(&self, reason: ReasonForClose) -> PositionState {
+        match &self.state {
+            PositionState::Closing(closing_reason) => PositionState::Closed(closing_reason.clone()),
+            _ => PositionState::Closed(reason),
         }
+    }
+
+    fn can_close_with(&self, reason: &ReasonForClose) -> bool {
+        self.state
+            .can_transition_to(&self.closed_state(reason.clone()))
+    }
+
+    fn delete(
+        &mut self,
+        close_price: Decimal,
+        reason: ReasonForClose,
+    ) -> Result<(), PositionError> {
+        self.update_state(self.closed_state(reason))?;
 
         self.close_price = close_price;
-        self.pnl += Self::unrealized_pnl(close_price, self.amount, self.asset_in_usd);
+        lot::close_lots(
+            &mut self.lots,
+            self.cost_basis_method,
+            self.amount.abs(),
+            close_price,
+            self.average_open_price,
+        );
+        self.pnl += Self::calc_unrealized_pnl(close_price, self.amount, self.asset_in_usd);
         self.pnl -= self.fee;
         self.amount = Decimal::new(0, 0);
         self.asset_in_usd = Decimal::new(0, 0);
@@ -413,32 +1373,72 @@ impl Position {
             self.amount,
             self.pnl
         );
+        Ok(())
     }
 
-    fn update_state(&mut self, new_state: PositionState) {
-        match new_state {
-            PositionState::Closing(_) => {
+    fn update_state(&mut self, new_state: PositionState) -> Result<(), PositionError> {
+        if !self.state.can_transition_to(&new_state) {
+            log::error!(
+                "Invalid transition of the position[{}]: {} -> {}",
+                self.id,
+                self.state,
+                new_state
+            );
+            return Err(PositionError::InvalidTransition {
+                id: self.id,
+                from: self.state.clone(),
+                to: new_state,
+            });
+        }
+
+        match (&self.state, &new_state) {
+            (_, PositionState::Closing(_)) => {
                 self.actual_hold_tick = self.tick_count;
                 self.tick_count = 0;
+                self.closing_timestamp = self.clock.now();
             }
-            PositionState::Open => match self.state {
-                PositionState::Ready => {
-                    self.actual_entry_tick = self.tick_count;
-                    self.tick_count = 0;
-                    self.set_open_time();
-                }
-                PositionState::Closing(_) => {
-                    return;
-                }
-                _ => {}
-            },
-            PositionState::Closed(_) => {
+            (PositionState::Ready, PositionState::Opening) => {
+                self.entry_timestamp = self.clock.now();
+            }
+            (PositionState::Ready | PositionState::Opening, PositionState::Open) => {
+                self.actual_entry_tick = self.tick_count;
+                self.tick_count = 0;
+                self.set_open_time();
+            }
+            (PositionState::Ready, PositionState::PartiallyOpen) => {
+                self.entry_timestamp = self.clock.now();
+                self.set_open_time();
+            }
+            (PositionState::Opening, PositionState::PartiallyOpen) => {
+                self.set_open_time();
+            }
+            (PositionState::PartiallyOpen, PositionState::Open) => {
+                self.actual_entry_tick = self.tick_count;
+                self.tick_count = 0;
+            }
+            (_, PositionState::Closed(_)) => {
                 self.set_close_time();
             }
             _ => {}
         }
 
-        self.state = new_state
+        self.state_history.push(StateTransition {
+            from: self.state.clone(),
+            to: new_state.clone(),
+            timestamp: self.clock.now(),
+        });
+        self.state = new_state;
+        Ok(())
+    }
+
+    /// Every state change of the position, oldest first.
+    pub fn state_history(&self) -> &[StateTransition] {
+        &self.state_history
+    }
+
+    /// Cause of the liquidation, when the exchange reported one.
+    pub fn liquidation_reason(&self) -> Option<&str> {
+        self.liquidation_reason.as_deref()
     }
 
     fn update_amount_and_pnl(
@@ -461,14 +1461,38 @@ impl Position {
             UpdateResult::Decreased
         };
 
-        let pnl = self.calculate_pnl_for_update(
-            &update_result,
-            prev_amount,
+        let closed_amount = match update_result {
+            UpdateResult::Decreased => prev_amount.abs() - self.amount.abs(),
+            _ => prev_amount.abs(),
+        };
+        let lot_pnl = lot::close_lots(
+            &mut self.lots,
+            self.cost_basis_method,
+            closed_amount,
             close_price,
-            prev_asset_in_usd,
+            self.average_open_price,
         );
+
+        let by_lot = matches!(update_result, UpdateResult::Decreased)
+            && self.cost_basis_method != CostBasisMethod::Average;
+        let pnl = if by_lot {
+            lot_pnl
+        } else {
+            self.calculate_pnl_for_update(
+                &update_result,
+                prev_amount,
+                close_price,
+                prev_asset_in_usd,
+            )
+        };
         self.realize_pnl(pnl);
 
+        if by_lot {
+            if let Some(average_open_price) = lot::average_open_price(&self.lots) {
+                self.average_open_price = average_open_price;
+            }
+        }
+
         update_result
     }
 
@@ -483,7 +1507,7 @@ impl Position {
             UpdateResult::Decreased => {
                 (close_price - self.average_open_price) * (prev_amount - self.amount)
             }
-            _ => Self::unrealized_pnl(close_price, prev_amount, prev_asset_in_usd),
+            _ => Self::calc_unrealized_pnl(close_price, prev_amount, prev_asset_in_usd),
         }
     }
 
@@ -507,24 +1531,37 @@ impl Position {
         self.asset_in_usd -= pnl;
     }
 
-    fn unrealized_pnl(price: Decimal, amount: Decimal, asset_in_usd: Decimal) -> Decimal {
+    fn calc_unrealized_pnl(price: Decimal, amount: Decimal, asset_in_usd: Decimal) -> Decimal {
         amount * price + asset_in_usd
     }
 
     pub fn update_counter(&mut self) {
+        self.record_tick(self.clock.now());
         self.tick_count += 1;
     }
 
-    pub fn should_close(&self, close_price: Decimal, use_trailing: bool) -> Option<ReasonForClose> {
+    /// Exit check at `close_price`. Only reads the trailing peak and the
+    /// other marks kept by `on_price`, the single price-update entry point.
+    pub fn should_close(&self, close_price: Decimal, use_trailing: bool) -> Option<ExitSignal> {
         if self.should_take_profit(close_price, use_trailing) {
-            return Some(ReasonForClose::TakeProfit);
+            let reason = if use_trailing {
+                ReasonForClose::TrailingStop
+            } else {
+                ReasonForClose::TakeProfit
+            };
+            return Some(ExitSignal::Close(reason));
         }
 
         if self.should_cut_loss(close_price) {
-            Some(ReasonForClose::CutLoss)
-        } else {
-            None
+            return Some(ExitSignal::Close(ReasonForClose::CutLoss));
+        }
+
+        if self.should_volatility_stop(close_price) {
+            return Some(ExitSignal::Close(ReasonForClose::TrailingStop));
         }
+
+        self.take_profit_reduction(close_price)
+            .map(|(level, amount)| ExitSignal::Reduce { amount, level })
     }
 
     pub fn pnl(&self) -> (Decimal, Decimal) {
@@ -579,12 +1616,66 @@ impl Position {
         self.open_timestamp
     }
 
-    pub fn open_time_str(&self) -> &str {
-        &self.open_time_str
+    pub fn close_timestamp(&self) -> i64 {
+        if self.close_timestamp == 0 && !self.legacy_close_time_str.is_empty() {
+            return DateTime::parse_from_str(&self.legacy_close_time_str, "%Y-%m-%dT%H:%M:%S%z")
+                .map(|time| time.timestamp())
+                .unwrap_or_default();
+        }
+        self.close_timestamp
+    }
+
+    pub fn open_time(&self) -> Option<DateTime<Utc>> {
+        Self::to_datetime(self.open_timestamp)
+    }
+
+    pub fn close_time(&self) -> Option<DateTime<Utc>> {
+        Self::to_datetime(self.close_timestamp())
+    }
+
+    /// Time from open to close, or to now while the position is still held.
+    /// `None` for a closed position whose close time is unknown.
+    pub fn holding_duration(&self) -> Option<Duration> {
+        let open_time = self.open_time()?;
+        let end_time = match self.close_time() {
+            Some(close_time) => close_time,
+            None if matches!(self.state, PositionState::Closed(_)) => return None,
+            None => Self::to_datetime(self.clock.now())?,
+        };
+        Some(end_time - open_time)
+    }
+
+    pub fn time_zone(&self) -> FixedOffset {
+        FixedOffset::east_opt(self.utc_offset_seconds).unwrap_or_else(|| Utc.fix())
+    }
+
+    pub fn set_time_zone(&mut self, time_zone: FixedOffset) {
+        self.record(PositionEvent::TimeZoneChanged {
+            previous: self.utc_offset_seconds,
+            utc_offset_seconds: time_zone.local_minus_utc(),
+            timestamp: self.clock.now(),
+        });
+        self.utc_offset_seconds = time_zone.local_minus_utc();
     }
 
-    pub fn close_time_str(&self) -> &str {
-        &self.close_time_str
+    pub fn open_time_str(&self) -> String {
+        self.open_time()
+            .map(|t| format_time(t, self.time_zone()))
+            .unwrap_or_default()
+    }
+
+    pub fn close_time_str(&self) -> String {
+        self.close_time()
+            .map(|t| format_time(t, self.time_zone()))
+            .unwrap_or_default()
+    }
+
+    fn to_datetime(timestamp: i64) -> Option<DateTime<Utc>> {
+        if timestamp == 0 {
+            None
+        } else {
+            DateTime::from_timestamp(timestamp, 0)
+        }
     }
 
     pub fn close_price(&self) -> Decimal {
@@ -611,24 +1702,42 @@ impl Position {
         self.last_oracle_price
     }
 
+    pub fn entry_indicators(&self) -> &IndicatorSnapshot {
+        &self.entry_indicators
+    }
+
+    pub fn exit_indicators(&self) -> Option<&IndicatorSnapshot> {
+        self.exit_indicators.as_ref()
+    }
+
+    pub fn set_exit_indicators(&mut self, indicators: IndicatorSnapshot) {
+        self.exit_indicators = Some(indicators);
+    }
+
+    /// Sets the entry indicators of a position opened by a fill rather than
+    /// built, e.g. the one split off by an inversion.
+    pub fn set_entry_indicators(&mut self, indicators: IndicatorSnapshot) {
+        self.entry_indicators = indicators;
+    }
+
     pub fn rsi(&self) -> (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal) {
-        self.rsi
+        self.entry_indicators.rsi.into()
     }
 
     pub fn atr(&self) -> (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal) {
-        self.atr
+        self.entry_indicators.atr.into()
     }
 
     pub fn adx(&self) -> (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal) {
-        self.adx
+        self.entry_indicators.adx.into()
     }
 
     pub fn stochastic(&self) -> (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal) {
-        self.stochastic
+        self.entry_indicators.stochastic.into()
     }
 
     pub fn price(&self) -> (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal) {
-        self.price
+        self.entry_indicators.price.into()
     }
 
     pub fn candle_pattern(
@@ -641,7 +1750,7 @@ impl Position {
         CandlePattern,
         CandlePattern,
     ) {
-        self.candle_pattern
+        self.entry_indicators.candle_pattern.into()
     }
 
     pub fn take_profit_ratio(&self) -> Decimal {
@@ -681,14 +1790,30 @@ impl Position {
     }
 
     pub fn should_open_expired(&self, close_price: Decimal) -> bool {
-        if matches!(self.state, PositionState::Open) {
-            self.tick_count > self.max_holding_tick_count
+        if self.is_holding() {
+            (self.tick_count > self.max_holding_tick_count
+                || self.has_elapsed(self.open_timestamp, self.max_holding_secs))
                 && !self.has_reached_take_profit(close_price)
         } else {
             false
         }
     }
 
+    pub fn max_holding_duration(&self) -> Option<Duration> {
+        self.max_holding_secs.map(Duration::seconds)
+    }
+
+    pub fn exit_timeout(&self) -> Option<Duration> {
+        self.exit_timeout_secs.map(Duration::seconds)
+    }
+
+    fn has_elapsed(&self, since: i64, limit_secs: Option<i64>) -> bool {
+        match limit_secs {
+            Some(limit_secs) => self.clock.now() - since > limit_secs,
+            None => false,
+        }
+    }
+
     pub fn take_profit_price(&self) -> Option<Decimal> {
         self.take_profit_price
     }
@@ -697,102 +1822,450 @@ impl Position {
         self.cut_loss_price
     }
 
-    fn is_trailing_stop_triggered(&self, close_price: Decimal) -> bool {
+    /// Feeds a market price. Maintains the high/low water marks, the
+    /// unrealized PnL excursions and the trailing peak whether or not exit
+    /// checks are run.
+    pub fn on_price(&mut self, price: Decimal, timestamp: i64) {
+        let marks = self.price_marks();
+        self.update_price_marks(price, timestamp);
+        let quiet = self.price_marks() == marks;
+        self.record_price(price, timestamp, quiet);
+    }
+
+    fn update_price_marks(&mut self, price: Decimal, timestamp: i64) {
+        self.last_price = Some(price);
+        self.last_price_timestamp = timestamp;
+
+        if self.amount.is_zero() {
+            return;
+        }
+
+        self.high_water_mark = Some(self.high_water_mark.map_or(price, |p| p.max(price)));
+        self.low_water_mark = Some(self.low_water_mark.map_or(price, |p| p.min(price)));
+
+        let unrealized_pnl = self.unrealized_pnl();
+        self.max_favorable_excursion = self.max_favorable_excursion.max(unrealized_pnl);
+        self.max_adverse_excursion = self.max_adverse_excursion.max(-unrealized_pnl);
+
+        self.update_trailing_peak(price);
+        self.update_atr_trailing_stop(price);
+        self.apply_stop_rules(price, timestamp);
+        self.mark_take_profit_targets(price, timestamp);
+    }
+
+    /// Everything a price update can change besides the last price.
+    fn price_marks(&self) -> PriceMarks {
+        PriceMarks {
+            high_water_mark: self.high_water_mark,
+            low_water_mark: self.low_water_mark,
+            max_favorable_excursion: self.max_favorable_excursion,
+            max_adverse_excursion: self.max_adverse_excursion,
+            trailing_peak_price: self.trailing_peak_price,
+            atr_trailing_stop_price: self.atr_trailing_stop_price,
+            cut_loss_price: self.cut_loss_price,
+            take_profit_hits: self
+                .take_profit_targets
+                .iter()
+                .filter(|target| target.is_hit())
+                .count(),
+        }
+    }
+
+    pub fn last_price(&self) -> Option<Decimal> {
+        self.last_price
+    }
+
+    pub fn last_price_timestamp(&self) -> i64 {
+        self.last_price_timestamp
+    }
+
+    pub fn high_water_mark(&self) -> Option<Decimal> {
+        self.high_water_mark
+    }
+
+    pub fn low_water_mark(&self) -> Option<Decimal> {
+        self.low_water_mark
+    }
+
+    /// Unrealized PnL at the last price, zero before any price update.
+    pub fn unrealized_pnl(&self) -> Decimal {
+        match self.last_price {
+            Some(price) if !self.amount.is_zero() => {
+                Self::calc_unrealized_pnl(price, self.amount, self.asset_in_usd)
+            }
+            _ => Decimal::ZERO,
+        }
+    }
+
+    /// Largest unrealized profit seen while holding the position.
+    pub fn max_favorable_excursion(&self) -> Decimal {
+        self.max_favorable_excursion
+    }
+
+    /// Largest unrealized loss seen while holding the position, as a
+    /// positive amount.
+    pub fn max_adverse_excursion(&self) -> Decimal {
+        self.max_adverse_excursion
+    }
+
+    fn reset_price_marks(&mut self) {
+        self.high_water_mark = None;
+        self.low_water_mark = None;
+        self.max_favorable_excursion = Decimal::ZERO;
+        self.max_adverse_excursion = Decimal::ZERO;
+        self.trailing_peak_price = None;
+        self.atr_trailing_stop_price = None;
+        for target in &mut self.take_profit_targets {
+            target.hit_timestamp = None;
+            target.reduced_amount = Decimal::ZERO;
+        }
+    }
+
+    /// Tracks the best price seen since the trailing stop was activated.
+    fn update_trailing_peak(&mut self, close_price: Decimal) {
+        if self.is_holding() {
+            self.trailing_peak_price = self.trailing_peak_at(close_price);
+        }
+    }
+
+    pub fn trailing_peak_price(&self) -> Option<Decimal> {
+        self.trailing_peak_price
+    }
+
+    pub fn trailing_stop(&self) -> &TrailingStop {
+        &self.trailing_stop
+    }
+
+    pub fn set_trailing_stop(&mut self, trailing_stop: TrailingStop) {
+        self.record(PositionEvent::TrailingStopChanged {
+            previous: self.trailing_stop.clone(),
+            trailing_stop: trailing_stop.clone(),
+            timestamp: self.clock.now(),
+        });
+        self.trailing_stop = trailing_stop;
+    }
+
+    /// Current trailing stop level, once the activation price was crossed.
+    pub fn trailing_stop_price(&self) -> Option<Decimal> {
+        self.trailing_stop_price_at(self.trailing_peak_price?)
+    }
+
+    fn trailing_peak_at(&self, close_price: Decimal) -> Option<Decimal> {
         let open_price = self.average_open_price;
 
-        let Some(tp_price) = self.take_profit_price else {
-            return false;
+        let Some(activation_price) = self.trailing_stop.activation_price(self.take_profit_price)
+        else {
+            return self.trailing_peak_price;
         };
 
-        let expected_profit = match self.position_type {
-            PositionType::Long => tp_price - open_price,
-            PositionType::Short => open_price - tp_price,
+        match self.position_type {
+            PositionType::Long if close_price >= activation_price => Some(
+                self.trailing_peak_price
+                    .unwrap_or(close_price.max(open_price))
+                    .max(close_price),
+            ),
+            PositionType::Short if close_price <= activation_price => Some(
+                self.trailing_peak_price
+                    .unwrap_or(close_price.min(open_price))
+                    .min(close_price),
+            ),
+            _ => self.trailing_peak_price,
+        }
+    }
+
+    fn trailing_stop_price_at(&self, peak: Decimal) -> Option<Decimal> {
+        let ctx = TrailingContext {
+            position_type: self.position_type.clone(),
+            open_price: self.average_open_price,
+            take_profit_price: self.take_profit_price,
+            activation_price: self.trailing_stop.activation_price(self.take_profit_price),
+            atr: self.current_atr(),
         };
+        self.trailing_stop.stop_price(&ctx, peak)
+    }
 
-        let trailing_stop_ratio = expected_profit / open_price * Decimal::new(5, 1);
+    /// ATR used by volatility based exits: the last value passed to
+    /// `update_atr`, else the first term of the entry ATR.
+    pub fn current_atr(&self) -> Option<Decimal> {
+        self.current_atr.or_else(|| self.entry_atr())
+    }
 
-        match self.position_type {
-            PositionType::Long => {
-                if let Some(peak) = *self.trailing_peak_price.borrow() {
-                    let stop_price = peak * (Decimal::ONE - trailing_stop_ratio);
-                    return close_price <= stop_price && close_price > open_price;
-                }
-            }
-            PositionType::Short => {
-                if let Some(trough) = *self.trailing_peak_price.borrow() {
-                    let stop_price = trough * (Decimal::ONE + trailing_stop_ratio);
-                    return close_price >= stop_price && close_price < open_price;
+    fn entry_atr(&self) -> Option<Decimal> {
+        self.entry_indicators
+            .atr
+            .get(0)
+            .filter(|atr| !atr.is_zero())
+    }
+
+    pub fn update_atr(&mut self, atr: Decimal) {
+        self.record(PositionEvent::AtrUpdated {
+            atr,
+            timestamp: self.clock.now(),
+        });
+        self.current_atr = Some(atr);
+    }
+
+    pub fn volatility_stops(&self) -> &[VolatilityStop] {
+        &self.volatility_stops
+    }
+
+    pub fn add_volatility_stop(&mut self, stop: VolatilityStop) {
+        self.record(PositionEvent::VolatilityStopAdded {
+            stop: stop.clone(),
+            timestamp: self.clock.now(),
+        });
+        self.volatility_stops.push(stop);
+    }
+
+    /// Tightest of the chandelier and ATR trailing stops.
+    pub fn volatility_stop_price(&self) -> Option<Decimal> {
+        let atr = self.current_atr()?;
+        self.volatility_stops
+            .iter()
+            .filter_map(|stop| match stop {
+                VolatilityStop::Chandelier { multiple } => {
+                    let extreme = match self.position_type {
+                        PositionType::Long => self.high_water_mark?,
+                        PositionType::Short => self.low_water_mark?,
+                    };
+                    Some(volatility_stop::offset(
+                        &self.position_type,
+                        extreme,
+                        multiple * atr,
+                    ))
                 }
+                VolatilityStop::AtrTrailing { .. } => self.atr_trailing_stop_price,
+                VolatilityStop::ScaledCutLoss { .. } => None,
+            })
+            .reduce(|a, b| volatility_stop::tighter(&self.position_type, a, b))
+    }
+
+    /// `cut_loss_price` widened by a `ScaledCutLoss` volatility stop. A stop
+    /// at or past the open price, e.g. moved there by a stop rule, is kept.
+    pub fn effective_cut_loss_price(&self) -> Option<Decimal> {
+        let cut_loss_price = self.cut_loss_price?;
+        let max_factor = self.volatility_stops.iter().find_map(|stop| match stop {
+            VolatilityStop::ScaledCutLoss { max_factor } => Some(*max_factor),
+            _ => None,
+        });
+        let (Some(max_factor), Some(entry_atr), Some(atr)) =
+            (max_factor, self.entry_atr(), self.current_atr())
+        else {
+            return Some(cut_loss_price);
+        };
+
+        let open_price = self.average_open_price;
+        let on_loss_side = match self.position_type {
+            PositionType::Long => cut_loss_price < open_price,
+            PositionType::Short => cut_loss_price > open_price,
+        };
+        if !on_loss_side {
+            return Some(cut_loss_price);
+        }
+
+        let factor = (atr / entry_atr).clamp(Decimal::ONE, max_factor);
+        Some(open_price - (open_price - cut_loss_price) * factor)
+    }
+
+    fn update_atr_trailing_stop(&mut self, price: Decimal) {
+        let Some(atr) = self.current_atr() else {
+            return;
+        };
+        let Some(multiple) = self.volatility_stops.iter().find_map(|stop| match stop {
+            VolatilityStop::AtrTrailing { multiple } => Some(*multiple),
+            _ => None,
+        }) else {
+            return;
+        };
+
+        let distance = multiple * atr;
+        let initial =
+            volatility_stop::offset(&self.position_type, self.average_open_price, distance);
+        let candidate = volatility_stop::offset(&self.position_type, price, distance);
+        let current = self.atr_trailing_stop_price.unwrap_or(initial);
+        self.atr_trailing_stop_price = Some(volatility_stop::tighter(
+            &self.position_type,
+            current,
+            candidate,
+        ));
+    }
+
+    fn should_volatility_stop(&self, close_price: Decimal) -> bool {
+        if !self.is_holding() {
+            return false;
+        }
+
+        self.volatility_stop_price().is_some_and(|stop| {
+            volatility_stop::is_stop_hit(&self.position_type, close_price, stop)
+        })
+    }
+
+    pub fn take_profit_targets(&self) -> &[TakeProfitTarget] {
+        &self.take_profit_targets
+    }
+
+    fn mark_take_profit_targets(&mut self, price: Decimal, timestamp: i64) {
+        if !self.is_holding() {
+            return;
+        }
+
+        for (level, target) in self.take_profit_targets.iter_mut().enumerate() {
+            if !target.is_hit() && target.level.is_reached(&self.position_type, price) {
+                target.hit_timestamp = Some(timestamp);
+                log::info!("Take-profit level {} is hit at {}", level, price);
             }
         }
+    }
 
-        false
+    /// Size of the position before the scale-out started.
+    fn scale_out_base_amount(&self) -> Decimal {
+        self.amount.abs()
+            + self
+                .take_profit_targets
+                .iter()
+                .map(|target| target.reduced_amount)
+                .sum::<Decimal>()
     }
 
-    pub fn should_take_profit(&self, close_price: Decimal, use_trailing: bool) -> bool {
+    /// First reached level that still has an amount to reduce.
+    fn take_profit_reduction(&self, close_price: Decimal) -> Option<(usize, Decimal)> {
         if !matches!(self.state, PositionState::Open) {
-            return false;
+            return None;
         }
 
-        let open_price = self.average_open_price;
-        let mut reached_tp = false;
-
-        if let Some(tp_price) = self.take_profit_price {
-            match self.position_type {
-                PositionType::Long => {
-                    if close_price >= tp_price {
-                        reached_tp = true;
-                        let mut peak = self.trailing_peak_price.borrow_mut();
-                        let current_peak = peak.get_or_insert(close_price.max(open_price));
-                        if close_price > *current_peak {
-                            *current_peak = close_price;
-                        }
-                    }
-                }
-                PositionType::Short => {
-                    if close_price <= tp_price {
-                        reached_tp = true;
-                        let mut trough = self.trailing_peak_price.borrow_mut();
-                        let current_trough = trough.get_or_insert(close_price.min(open_price));
-                        if close_price < *current_trough {
-                            *current_trough = close_price;
-                        }
-                    }
-                }
+        let base_amount = self.scale_out_base_amount();
+        self.take_profit_targets
+            .iter()
+            .enumerate()
+            .filter(|(_, target)| {
+                target.is_hit() || target.level.is_reached(&self.position_type, close_price)
+            })
+            .map(|(level, target)| {
+                let amount = target.remaining_amount(base_amount).min(self.amount.abs());
+                (level, amount)
+            })
+            .find(|(_, amount)| !amount.is_zero())
+    }
+
+    /// Attributes a decrease, already applied to `amount`, to the hit
+    /// levels in ladder order.
+    fn attribute_reduction(&mut self, amount: Decimal) {
+        let base_amount = self.scale_out_base_amount() + amount;
+        let mut unattributed = amount;
+
+        for target in self.take_profit_targets.iter_mut() {
+            if unattributed.is_zero() {
+                break;
             }
-        } else {
+            if !target.is_hit() {
+                continue;
+            }
+            let attributed = target.remaining_amount(base_amount).min(unattributed);
+            target.reduced_amount += attributed;
+            unattributed -= attributed;
+        }
+    }
+
+    pub fn stop_rules(&self) -> &[StopRule] {
+        &self.stop_rules
+    }
+
+    pub fn add_stop_rule(&mut self, rule: StopRule) {
+        self.record(PositionEvent::StopRuleAdded {
+            rule: rule.clone(),
+            timestamp: self.clock.now(),
+        });
+        self.stop_rules.push(rule);
+    }
+
+    /// Changes made to `cut_loss_price` by the stop rules, oldest first.
+    pub fn stop_history(&self) -> &[StopModification] {
+        &self.stop_history
+    }
+
+    fn apply_stop_rules(&mut self, price: Decimal, timestamp: i64) {
+        if !self.is_holding() || self.stop_rules.is_empty() {
+            return;
+        }
+
+        let fee_per_unit = self.fee / self.amount.abs();
+        let candidates: Vec<_> = self
+            .stop_rules
+            .iter()
+            .filter_map(|rule| {
+                let stop = rule.stop_price(
+                    &self.position_type,
+                    self.average_open_price,
+                    fee_per_unit,
+                    price,
+                )?;
+                Some((stop, rule.reason()))
+            })
+            .collect();
+
+        for (stop, reason) in candidates {
+            let previous = self.cut_loss_price;
+            if previous.is_some_and(|current| {
+                volatility_stop::tighter(&self.position_type, current, stop) == current
+            }) {
+                continue;
+            }
+
+            self.cut_loss_price = Some(stop);
+            self.stop_history.push(StopModification {
+                previous,
+                new: stop,
+                reason,
+                timestamp,
+            });
+            log::info!(
+                "The stop is moved by {:?}: {:?} -> {}",
+                reason,
+                previous,
+                stop
+            );
+        }
+    }
+
+    fn is_trailing_stop_triggered(&self, close_price: Decimal) -> bool {
+        let open_price = self.average_open_price;
+
+        let Some(peak) = self.trailing_peak_at(close_price) else {
+            return false;
+        };
+        let Some(stop_price) = self.trailing_stop_price_at(peak) else {
+            return false;
+        };
+
+        match self.position_type {
+            PositionType::Long => close_price <= stop_price && close_price > open_price,
+            PositionType::Short => close_price >= stop_price && close_price < open_price,
+        }
+    }
+
+    pub fn should_take_profit(&self, close_price: Decimal, use_trailing: bool) -> bool {
+        if !self.is_holding() {
             return false;
         }
 
+        let open_price = self.average_open_price;
+
         if !use_trailing {
-            return reached_tp;
+            return match (self.take_profit_price, &self.position_type) {
+                (Some(tp_price), PositionType::Long) => close_price >= tp_price,
+                (Some(tp_price), PositionType::Short) => close_price <= tp_price,
+                (None, _) => false,
+            };
         }
 
         let triggered = self.is_trailing_stop_triggered(close_price);
 
-        match self.position_type {
-            PositionType::Long => {
-                if let Some(peak) = *self.trailing_peak_price.borrow() {
-                    let expected = self.take_profit_price.unwrap() - open_price;
-                    let ratio = expected / open_price * Decimal::new(5, 1);
-                    let stop = peak * (Decimal::ONE - ratio);
-                    log::warn!(
-                        "Trailing Stop [Long][{}]: {} - price: {:.2}, open: {:.2}, peak: {:.2}, stop: {:.2}, ratio: {:.4}",
-                        self.id, triggered, close_price, open_price, peak, stop, ratio
-                    );
-                }
-            }
-            PositionType::Short => {
-                if let Some(trough) = *self.trailing_peak_price.borrow() {
-                    let expected = open_price - self.take_profit_price.unwrap();
-                    let ratio = expected / open_price * Decimal::new(5, 1);
-                    let stop = trough * (Decimal::ONE + ratio);
-                    log::warn!(
-                        "Trailing Stop [Short][{}]: {} - price: {:.2}, open: {:.2}, trough: {:.2}, stop: {:.2}, ratio: {:.4}",
-                        self.id, triggered, close_price, open_price, trough, stop, ratio
-                    );
-                }
-            }
+        if let Some(peak) = self.trailing_peak_at(close_price) {
+            let stop = self.trailing_stop_price_at(peak).unwrap_or_default();
+            log::warn!(
+                "Trailing Stop [{}][{}]: {} - price: {:.2}, open: {:.2}, peak: {:.2}, stop: {:.2}, policy: {:?}",
+                self.position_type, self.id, triggered, close_price, open_price, peak, stop, self.trailing_stop.policy
+            );
         }
 
         triggered
@@ -821,11 +2294,11 @@ impl Position {
     }
 
     fn should_cut_loss(&self, close_price: Decimal) -> bool {
-        if !matches!(self.state, PositionState::Open) {
+        if !self.is_holding() {
             return false;
         }
 
-        match self.cut_loss_price {
+        match self.effective_cut_loss_price() {
             Some(cut_loss_price) => {
                 if self.position_type == PositionType::Long {
                     close_price <= cut_loss_price
@@ -839,27 +2312,40 @@ impl Position {
 
     pub fn should_cancel_closing(&self) -> bool {
         match self.state {
-            PositionState::Closing(_) => self.tick_count > self.exit_timeout_tick_count,
+            PositionState::Closing(_) => {
+                self.tick_count > self.exit_timeout_tick_count
+                    || self.has_elapsed(self.closing_timestamp, self.exit_timeout_secs)
+            }
             _ => false,
         }
     }
 
-    pub fn cancel_closing(&mut self) {
+    pub fn cancel_closing(&mut self) -> Result<(), PositionError> {
         if !matches!(self.state, PositionState::Closing(_)) {
-            log::warn!("cancel_closing: invalid state: {:?}", self);
+            return Err(PositionError::InvalidTransition {
+                id: self.id,
+                from: self.state.clone(),
+                to: PositionState::Open,
+            });
         }
-        self.state = PositionState::Open;
+
+        self.record(PositionEvent::ClosingCancelled {
+            timestamp: self.clock.now(),
+        });
+        self.update_state(PositionState::Open)
+    }
+
+    /// Replaces the clock, e.g. after deserialization.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
     }
 
     fn set_open_time(&mut self) {
-        let (timestamp, time_str) = get_local_time();
-        self.open_timestamp = timestamp;
-        self.open_time_str = time_str;
+        self.open_timestamp = self.clock.now();
     }
 
     fn set_close_time(&mut self) {
-        let (_, time_str) = get_local_time();
-        self.close_time_str = time_str;
+        self.close_timestamp = self.clock.now();
     }
 
     fn format_position(&self, current_price: Decimal) -> String {
@@ -867,7 +2353,8 @@ impl Position {
         let take_profit_price = self.take_profit_price.unwrap_or_default();
         let cut_loss_price = self.cut_loss_price.unwrap_or_default();
 
-        let unrealized_pnl = Self::unrealized_pnl(current_price, self.amount, self.asset_in_usd);
+        let unrealized_pnl =
+            Self::calc_unrealized_pnl(current_price, self.amount, self.asset_in_usd);
         let decimal_100 = Decimal::new(100, 0);
 
         format!(
@@ -899,71 +2386,681 @@ impl Position {
         )
     }
 
+    /// Links an order to this position. Opening orders must be on the
+    /// position side and closing orders on the opposite side.
+    pub fn add_order(&mut self, order: Order) -> Result<(), PositionError> {
+        if order.position_id() != Some(self.id) {
+            return Err(PositionError::OrderMismatch {
+                id: self.id,
+                order_id: order.id().to_owned(),
+                position_id: order.position_id(),
+            });
+        }
+
+        let expected = match order.order_type() {
+            OrderType::OpenOrder => self.position_type.clone(),
+            OrderType::CloseOrder => self.position_type.opposite(),
+        };
+        if order.side() != expected {
+            return Err(PositionError::SideMismatch {
+                id: self.id,
+                order_id: order.id().to_owned(),
+                expected,
+                actual: order.side(),
+            });
+        }
+
+        if order.order_type() == OrderType::OpenOrder && self.state == PositionState::Ready {
+            self.start_entry()?;
+        }
+
+        self.orders.push(order);
+        Ok(())
+    }
+
+    pub fn orders(&self) -> &[Order] {
+        &self.orders
+    }
+
+    pub fn order(&self, order_id: &str) -> Option<&Order> {
+        self.orders.iter().find(|o| o.id() == order_id)
+    }
+
+    pub fn order_mut(&mut self, order_id: &str) -> Option<&mut Order> {
+        self.orders.iter_mut().find(|o| o.id() == order_id)
+    }
+
+    pub fn working_orders(&self) -> impl Iterator<Item = &Order> {
+        self.orders.iter().filter(|o| o.state().is_working())
+    }
+
+    /// Drops orders that can no longer be filled and returns them.
+    pub fn remove_finished_orders(&mut self) -> Vec<Order> {
+        let (working, finished) = self.orders.drain(..).partition(|o| o.state().is_working());
+        self.orders = working;
+        finished
+    }
+
+    /// Signed amount (long positive) still waiting to be filled by the
+    /// working orders of this position.
+    pub fn pending_exposure(&self) -> Decimal {
+        self.working_orders().map(|o| o.pending_exposure()).sum()
+    }
+
     pub fn get_info(&self, current_price: Decimal) -> Option<String> {
         if self.amount.is_zero() {
             None
         } else {
-            Some(format!("{}", self.format_position(current_price)))
+            Some(self.format_position(current_price))
         }
     }
 }
 
-impl Order {
-    pub fn new(id: String, amount: Decimal, entry_timeout_tick_count: u32) -> Order {
-        Self {
-            id,
-            unfilled_amount: amount,
-            state: OrderState::Open,
-            tick_count: 0,
-            entry_timeout_tick_count,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Clock, TakeProfitLevel};
+    use rust_decimal_macros::dec;
+
+    fn fill(fill_id: &str, position_type: PositionType, price: Decimal, amount: Decimal) -> Fill {
+        Fill {
+            fill_id: Some(fill_id.to_owned()),
+            position_type,
+            price,
+            amount,
+            asset_in_usd: price * amount,
+            current_price: price,
+            ..Fill::default()
         }
     }
 
-    pub fn on_filled(&mut self, amount: Decimal) -> Result<(), ()> {
-        if matches!(self.state, OrderState::Filled) {
-            log::warn!(
-                "The order is filled unexpectedly: id = {}, state = {}, amount = {}",
-                self.id,
-                self.state,
-                amount
-            );
-            return Err(());
+    fn sequenced(fill: Fill, sequence: u64) -> Fill {
+        Fill {
+            sequence: Some(sequence),
+            ..fill
         }
+    }
+
+    fn builder(clock: &Arc<ManualClock>) -> PositionBuilder {
+        PositionBuilder::new(1, "fund", "BTC").clock(clock.clone())
+    }
+
+    fn to_json(position: &Position) -> String {
+        serde_json::to_string(position).unwrap()
+    }
 
-        self.unfilled_amount -= amount;
-        if self.unfilled_amount.is_zero() {
-            self.state = OrderState::Filled;
+    #[test]
+    fn replay_rebuilds_the_live_position() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let builder = builder(&clock).take_profit_level(TakeProfitLevel::new(dec!(130), dec!(0.5)));
+        let mut position = builder.clone().build().unwrap();
+
+        position
+            .apply_fill(sequenced(
+                fill("a", PositionType::Long, dec!(100), dec!(1)),
+                1,
+            ))
+            .unwrap();
+        for price in [dec!(101), dec!(99), dec!(99), dec!(104)] {
+            clock.advance(1);
+            position.on_price(price, clock.now());
+            position.update_counter();
+        }
+        position
+            .apply_fill(sequenced(
+                fill("c", PositionType::Long, dec!(110), dec!(1)),
+                3,
+            ))
+            .unwrap();
+        position
+            .apply_fill(sequenced(
+                fill("b", PositionType::Long, dec!(105), dec!(1)),
+                2,
+            ))
+            .unwrap();
+        position.add_stop_rule(StopRule::BreakEven {
+            trigger: dec!(5),
+            offset: dec!(0),
+        });
+        clock.advance(1);
+        position.on_price(dec!(131), clock.now());
+        position.bust_fill("c").unwrap();
+        position.request_close(ReasonForClose::Manual).unwrap();
+        position
+            .apply_fill(fill("d", PositionType::Short, dec!(131), dec!(2)))
+            .unwrap();
+
+        let replayed = Position::replay(builder, position.events()).unwrap();
+        assert_eq!(to_json(&replayed), to_json(&position));
+    }
+
+    #[test]
+    fn busted_fill_is_removed_and_its_redelivery_rejected() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut position = builder(&clock).build().unwrap();
+        position
+            .apply_fill(fill("a", PositionType::Long, dec!(100), dec!(1)))
+            .unwrap();
+        position
+            .apply_fill(fill("b", PositionType::Long, dec!(110), dec!(1)))
+            .unwrap();
+
+        position.bust_fill("b").unwrap();
+        assert_eq!(position.amount(), dec!(1));
+        assert_eq!(position.average_open_price(), dec!(100));
+
+        let redelivered = position.apply_fill(fill("b", PositionType::Long, dec!(110), dec!(1)));
+        assert!(matches!(
+            redelivered,
+            Err(PositionError::DuplicateFill { .. })
+        ));
+        assert!(matches!(
+            position.bust_fill("b"),
+            Err(PositionError::UnknownFill { .. })
+        ));
+        assert_eq!(position.amount(), dec!(1));
+    }
+
+    #[test]
+    fn late_fill_is_applied_in_sequence() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let fills = [
+            sequenced(fill("a", PositionType::Long, dec!(100), dec!(1)), 1),
+            sequenced(fill("b", PositionType::Long, dec!(110), dec!(1)), 2),
+            sequenced(fill("c", PositionType::Long, dec!(120), dec!(1)), 3),
+        ];
+
+        let mut in_order = builder(&clock).build().unwrap();
+        for fill in fills.iter().cloned() {
+            in_order.apply_fill(fill).unwrap();
         }
 
-        log::info!(
-            "Order filled: id = {}, state = {}, unfilled_amount = {}",
-            self.id,
-            self.state,
-            self.unfilled_amount
+        let mut late = builder(&clock).build().unwrap();
+        late.apply_fill(fills[0].clone()).unwrap();
+        late.apply_fill(fills[2].clone()).unwrap();
+        let outcome = late.apply_fill(fills[1].clone()).unwrap();
+
+        assert!(matches!(outcome, FillOutcome::Reordered));
+        let sequences: Vec<_> = late
+            .events()
+            .iter()
+            .filter_map(|event| match event {
+                PositionEvent::Filled { fill, .. } => fill.sequence,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sequences, vec![1, 2, 3]);
+        assert_eq!(late.amount(), in_order.amount());
+        assert_eq!(late.average_open_price(), in_order.average_open_price());
+        assert_eq!(late.lots(), in_order.lots());
+    }
+
+    #[test]
+    fn early_fill_is_buffered_once_the_entry_side_is_known() {
+        let clock = Arc::new(ManualClock::new(1_000));
+
+        let mut ready = builder(&clock).build().unwrap();
+        let outcome = ready
+            .apply_fill(fill("a", PositionType::Short, dec!(100), dec!(1)))
+            .unwrap();
+        assert!(matches!(outcome, FillOutcome::Applied));
+        assert_eq!(ready.position_type(), PositionType::Short);
+
+        let mut opening = builder(&clock).build().unwrap();
+        opening.start_entry().unwrap();
+        let close = fill("close", PositionType::Short, dec!(110), dec!(1));
+        let outcome = opening.apply_fill(close.clone()).unwrap();
+        assert!(matches!(outcome, FillOutcome::Buffered));
+        assert!(matches!(
+            opening.apply_fill(close),
+            Err(PositionError::DuplicateFill { .. })
+        ));
+
+        let outcome = opening
+            .apply_fill(fill("entry", PositionType::Long, dec!(100), dec!(1)))
+            .unwrap();
+        assert!(matches!(outcome, FillOutcome::Applied));
+        assert!(opening.pending_fills().is_empty());
+        assert!(matches!(opening.state(), PositionState::Closed(_)));
+
+        let mut opted_in = builder(&clock).buffer_early_fills(true).build().unwrap();
+        let outcome = opted_in
+            .apply_fill(fill("close", PositionType::Short, dec!(110), dec!(1)))
+            .unwrap();
+        assert!(matches!(outcome, FillOutcome::Buffered));
+        assert_eq!(opted_in.state(), PositionState::Ready);
+        opted_in
+            .apply_fill(fill("entry", PositionType::Long, dec!(100), dec!(1)))
+            .unwrap();
+        assert!(matches!(opted_in.state(), PositionState::Closed(_)));
+        assert_eq!(opted_in.pnl().0, dec!(10));
+    }
+
+    #[test]
+    fn transitions_follow_the_table() {
+        use PositionState::*;
+        let reason = ReasonForClose::Manual;
+        let states = [
+            Ready,
+            Opening,
+            PartiallyOpen,
+            Open,
+            Reducing(reason.clone()),
+            Closing(reason.clone()),
+            Closed(reason.clone()),
+        ];
+        let allowed: &[(usize, &[usize])] = &[
+            (0, &[1, 2, 3]),
+            (1, &[2, 3, 6]),
+            (2, &[3, 5, 6]),
+            (3, &[4, 5, 6]),
+            (4, &[3, 5, 6]),
+            (5, &[3, 6]),
+            (6, &[]),
+        ];
+        for (from, to) in allowed {
+            for (next, state) in states.iter().enumerate() {
+                assert_eq!(
+                    states[*from].can_transition_to(state),
+                    to.contains(&next),
+                    "{} -> {}",
+                    states[*from],
+                    state
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn state_history_records_each_transition() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut position = builder(&clock).build().unwrap();
+        position
+            .apply_fill(fill("a", PositionType::Long, dec!(100), dec!(1)))
+            .unwrap();
+        assert!(matches!(
+            position.cancel_closing(),
+            Err(PositionError::InvalidTransition { .. })
+        ));
+        clock.advance(5);
+        position.request_close(ReasonForClose::Manual).unwrap();
+        position
+            .apply_fill(fill("b", PositionType::Short, dec!(105), dec!(1)))
+            .unwrap();
+
+        let history: Vec<_> = position
+            .state_history()
+            .iter()
+            .map(|transition| (transition.to.clone(), transition.timestamp))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (PositionState::Open, 1_000),
+                (PositionState::Closing(ReasonForClose::Manual), 1_005),
+                (PositionState::Closed(ReasonForClose::Manual), 1_005),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejected_liquidation_leaves_the_position_untouched() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let builder = builder(&clock);
+
+        let mut ready = builder.clone().build().unwrap();
+        assert!(ready
+            .on_liquidated(dec!(90), dec!(1), true, Some("margin".to_owned()))
+            .is_err());
+        assert!(ready.events().is_empty());
+        assert_eq!(ready.fee(), Decimal::ZERO);
+        assert_eq!(ready.liquidation_reason(), None);
+
+        let mut closed = builder.clone().build().unwrap();
+        closed
+            .apply_fill(fill("a", PositionType::Long, dec!(100), dec!(1)))
+            .unwrap();
+        closed
+            .on_liquidated(dec!(90), dec!(1), true, Some("margin".to_owned()))
+            .unwrap();
+        let events = closed.events().len();
+        assert!(closed.on_liquidated(dec!(90), dec!(1), true, None).is_err());
+        assert_eq!(closed.events().len(), events);
+        assert_eq!(closed.fee(), dec!(1));
+        assert_eq!(closed.liquidation_reason(), Some("margin"));
+
+        let replayed = Position::replay(builder, closed.events()).unwrap();
+        assert_eq!(to_json(&replayed), to_json(&closed));
+    }
+
+    #[test]
+    fn scaled_cut_loss_keeps_a_stop_moved_past_the_entry() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let atr = (dec!(2), dec!(2), dec!(2), dec!(2), dec!(2), dec!(2));
+        let mut position = builder(&clock)
+            .atr(atr)
+            .volatility_stop(VolatilityStop::ScaledCutLoss {
+                max_factor: dec!(3),
+            })
+            .stop_rule(StopRule::BreakEven {
+                trigger: dec!(5),
+                offset: dec!(1),
+            })
+            .build()
+            .unwrap();
+        let mut entry = fill("a", PositionType::Long, dec!(100), dec!(1));
+        entry.cut_loss_price = Some(dec!(95));
+        position.apply_fill(entry).unwrap();
+
+        position.update_atr(dec!(4));
+        assert_eq!(position.effective_cut_loss_price(), Some(dec!(90)));
+
+        position.on_price(dec!(106), clock.now());
+        assert_eq!(position.cut_loss_price(), Some(dec!(101)));
+        assert_eq!(position.effective_cut_loss_price(), Some(dec!(101)));
+    }
+
+    #[test]
+    fn split_opens_the_residual_without_the_other_side_levels() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut position = builder(&clock)
+            .inversion_policy(InversionPolicy::Split)
+            .take_profit_level(TakeProfitLevel::new(dec!(130), dec!(0.5)))
+            .trailing_stop(TrailingStop {
+                activation: TrailingActivation::Price(dec!(120)),
+                ..TrailingStop::default()
+            })
+            .build()
+            .unwrap();
+        position
+            .apply_fill(fill("a", PositionType::Long, dec!(100), dec!(1)))
+            .unwrap();
+
+        let outcome = position
+            .apply_fill(fill("b", PositionType::Short, dec!(99), dec!(2)))
+            .unwrap();
+        let FillOutcome::Split(mut residual) = outcome else {
+            panic!("not split: {:?}", outcome);
+        };
+
+        assert_eq!(
+            position.state(),
+            PositionState::Closed(ReasonForClose::Inverted)
+        );
+        assert_eq!(position.pnl().0, dec!(-1));
+        assert_eq!(residual.position_type(), PositionType::Short);
+        assert_eq!(residual.amount(), dec!(-1));
+        assert_eq!(residual.average_open_price(), dec!(99));
+        assert!(residual.take_profit_targets().is_empty());
+        assert_eq!(
+            residual.trailing_stop().activation,
+            TrailingActivation::TakeProfit
         );
+        assert_eq!(residual.id(), position.id());
 
-        return Ok(());
+        residual.on_price(dec!(100), clock.now());
+        assert_eq!(residual.should_close(dec!(100), false), None);
+        residual.set_id(2);
+        assert_eq!(residual.id(), 2);
     }
 
-    pub fn should_cancel_order(&self) -> bool {
-        if matches!(self.state, OrderState::Open) {
-            self.tick_count > self.entry_timeout_tick_count
-        } else {
-            false
-        }
+    /// A closed position as serialized by 3.x.
+    fn legacy_json(close_time_str: &str) -> String {
+        format!(
+            r#"{{"id":3,"fund_name":"fund","state":{{"Closed":"my-signal"}},"token_name":"BTC",
+            "tick_count":4,"actual_entry_tick":1,"actual_hold_tick":2,"max_holding_tick_count":10,
+            "exit_timeout_tick_count":5,"open_time_str":"2024-01-02T03:04:05+0100",
+            "open_timestamp":1704161045,"close_time_str":"{}","average_open_price":"100",
+            "position_type":"Short","target_price":"0","take_profit_price":"90",
+            "cut_loss_price":null,"close_price":"95","close_asset_in_usd":"0","amount":"0",
+            "asset_in_usd":"0","pnl":"5","fee":"0","trailing_peak_price":null,
+            "atr":["1","2","3","4","5","6"],"take_profit_ratio":"0","atr_spread":"0",
+            "risk_reward":"0","atr_term":"0","tick_spread":0,"bias_ticks":0,"last_volume":null,
+            "last_num_trades":null,"last_funding_rate":null,"last_open_interest":null,
+            "last_oracle_price":null}}"#,
+            close_time_str
+        )
     }
 
-    pub fn update_counter(&mut self) {
-        if matches!(self.state, OrderState::Open) {
-            self.tick_count += 1;
+    #[test]
+    fn legacy_position_keeps_its_close_time() {
+        let position: Position =
+            serde_json::from_str(&legacy_json("2024-01-02T04:04:05+0100")).unwrap();
+        assert_eq!(
+            position.state(),
+            PositionState::Closed(ReasonForClose::Other("my-signal".to_owned()))
+        );
+        assert_eq!(position.close_timestamp(), 1_704_164_645);
+        assert_eq!(position.holding_duration(), Some(Duration::hours(1)));
+        assert_eq!(position.time_zone(), default_time_zone());
+
+        let reserialized: Position =
+            serde_json::from_str(&serde_json::to_string(&position).unwrap()).unwrap();
+        assert_eq!(reserialized.close_timestamp(), 1_704_164_645);
+
+        let position: Position = serde_json::from_str(&legacy_json("")).unwrap();
+        assert_eq!(position.close_time(), None);
+        assert_eq!(position.holding_duration(), None);
+    }
+
+    #[test]
+    fn stop_rules_only_tighten_and_apply_from_when_they_are_added() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let builder = builder(&clock);
+        let mut position = builder.clone().build().unwrap();
+        let mut entry = fill("a", PositionType::Long, dec!(100), dec!(1));
+        entry.cut_loss_price = Some(dec!(95));
+        position.apply_fill(entry.clone()).unwrap();
+        position
+            .apply_fill(Fill {
+                fill_id: Some("b".to_owned()),
+                ..entry
+            })
+            .unwrap();
+
+        clock.advance(1);
+        position.on_price(dec!(110), clock.now());
+        assert_eq!(position.cut_loss_price(), Some(dec!(95)));
+
+        clock.advance(1);
+        position.add_stop_rule(StopRule::Ratchet {
+            trigger: dec!(5),
+            step: dec!(2),
+            distance: dec!(3),
+        });
+        for price in [dec!(105), dec!(109), dec!(106)] {
+            clock.advance(1);
+            position.on_price(price, clock.now());
         }
-    }
+        assert_eq!(position.cut_loss_price(), Some(dec!(106)));
+        let history: Vec<_> = position
+            .stop_history()
+            .iter()
+            .map(|modification| {
+                (
+                    modification.previous,
+                    modification.new,
+                    modification.timestamp,
+                )
+            })
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (Some(dec!(95)), dec!(102), 1_003),
+                (Some(dec!(102)), dec!(106), 1_004),
+            ]
+        );
 
-    pub fn id(&self) -> &str {
-        &self.id
+        position.bust_fill("b").unwrap();
+        assert_eq!(position.stop_rules().len(), 1);
+        assert_eq!(position.stop_history().len(), 2);
+        let replayed = Position::replay(builder, position.events()).unwrap();
+        assert_eq!(to_json(&replayed), to_json(&position));
+    }
+
+    #[test]
+    fn take_profit_ladder_scales_out_level_by_level() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut position = builder(&clock)
+            .take_profit_level(TakeProfitLevel::new(dec!(110), dec!(0.5)))
+            .take_profit_level(TakeProfitLevel::new(dec!(120), dec!(0.5)))
+            .build()
+            .unwrap();
+        position
+            .apply_fill(fill("a", PositionType::Long, dec!(100), dec!(2)))
+            .unwrap();
+
+        position.on_price(dec!(111), clock.now());
+        assert_eq!(
+            position.should_close(dec!(111), false),
+            Some(ExitSignal::Reduce {
+                amount: dec!(1),
+                level: 0
+            })
+        );
+        position
+            .apply_fill(fill("b", PositionType::Short, dec!(111), dec!(1)))
+            .unwrap();
+        assert_eq!(position.take_profit_targets()[0].reduced_amount, dec!(1));
+
+        position.on_price(dec!(115), clock.now());
+        assert_eq!(position.should_close(dec!(115), false), None);
+
+        position.on_price(dec!(121), clock.now());
+        assert_eq!(
+            position.should_close(dec!(121), false),
+            Some(ExitSignal::Reduce {
+                amount: dec!(1),
+                level: 1
+            })
+        );
     }
 
-    pub fn state(&self) -> OrderState {
-        self.state.clone()
+    #[test]
+    fn reduce_request_is_settled_by_its_fills() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut position = builder(&clock).build().unwrap();
+        position
+            .apply_fill(fill("a", PositionType::Long, dec!(100), dec!(2)))
+            .unwrap();
+
+        assert!(matches!(
+            position.request_reduce(dec!(3), ReasonForClose::Manual),
+            Err(PositionError::InvalidAmount { .. })
+        ));
+        position
+            .request_reduce(dec!(1), ReasonForClose::RiskLimit)
+            .unwrap();
+        assert_eq!(
+            position.state(),
+            PositionState::Reducing(ReasonForClose::RiskLimit)
+        );
+
+        position
+            .apply_fill(fill("b", PositionType::Short, dec!(110), dec!(0.4)))
+            .unwrap();
+        assert_eq!(position.pending_reduce_amount(), dec!(0.6));
+        assert!(matches!(position.state(), PositionState::Reducing(_)));
+
+        position
+            .apply_fill(fill("c", PositionType::Short, dec!(110), dec!(0.6)))
+            .unwrap();
+        assert_eq!(position.state(), PositionState::Open);
+        assert_eq!(position.amount(), dec!(1));
+        assert_eq!(position.pnl().0, dec!(10));
+
+        position
+            .request_reduce(dec!(0.5), ReasonForClose::Manual)
+            .unwrap();
+        position.cancel_reduce().unwrap();
+        assert_eq!(position.state(), PositionState::Open);
+        assert_eq!(position.pending_reduce_amount(), Decimal::ZERO);
+    }
+
+    #[test]
+    fn entry_timeout_depends_on_the_filled_fraction() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let entry_builder = builder(&clock)
+            .entry_amount(dec!(2))
+            .min_entry_fraction(dec!(0.25))
+            .entry_timeout(Duration::seconds(60));
+        let started = |filled: Option<Decimal>| {
+            let mut position = entry_builder.clone().build().unwrap();
+            position.start_entry().unwrap();
+            if let Some(amount) = filled {
+                position
+                    .apply_fill(fill("a", PositionType::Long, dec!(100), amount))
+                    .unwrap();
+            }
+            position
+        };
+
+        let mut unfilled = started(None);
+        let mut partial = started(Some(dec!(0.6)));
+        let small = started(Some(dec!(0.4)));
+        let full = started(Some(dec!(2)));
+        assert_eq!(unfilled.state(), PositionState::Opening);
+        assert_eq!(partial.state(), PositionState::PartiallyOpen);
+        assert_eq!(full.state(), PositionState::Open);
+        assert_eq!(partial.entry_timeout_action(), None);
+
+        clock.advance(61);
+        assert_eq!(
+            unfilled.entry_timeout_action(),
+            Some(EntryTimeoutAction::CancelEntry)
+        );
+        assert_eq!(
+            partial.entry_timeout_action(),
+            Some(EntryTimeoutAction::KeepPartial {
+                unfilled_amount: dec!(1.4)
+            })
+        );
+        assert_eq!(
+            small.entry_timeout_action(),
+            Some(EntryTimeoutAction::UnwindPartial {
+                filled_amount: dec!(0.4),
+                unfilled_amount: dec!(1.6)
+            })
+        );
+        assert_eq!(full.entry_timeout_action(), None);
+
+        unfilled.cancel_entry().unwrap();
+        assert_eq!(
+            unfilled.state(),
+            PositionState::Closed(ReasonForClose::Expired)
+        );
+        partial.finish_entry().unwrap();
+        assert_eq!(partial.state(), PositionState::Open);
+        assert_eq!(partial.entry_timeout_action(), None);
+    }
+
+    #[test]
+    fn volatility_stops_follow_the_price_and_never_loosen() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut position = builder(&clock)
+            .volatility_stop(VolatilityStop::Chandelier { multiple: dec!(3) })
+            .volatility_stop(VolatilityStop::AtrTrailing { multiple: dec!(2) })
+            .build()
+            .unwrap();
+        position
+            .apply_fill(fill("a", PositionType::Long, dec!(100), dec!(1)))
+            .unwrap();
+        assert_eq!(position.volatility_stop_price(), None);
+
+        position.update_atr(dec!(2));
+        position.on_price(dec!(110), clock.now());
+        assert_eq!(position.volatility_stop_price(), Some(dec!(106)));
+
+        position.on_price(dec!(107), clock.now());
+        assert_eq!(position.volatility_stop_price(), Some(dec!(106)));
+        assert_eq!(position.should_close(dec!(107), false), None);
+        assert_eq!(
+            position.should_close(dec!(105.5), false),
+            Some(ExitSignal::Close(ReasonForClose::TrailingStop))
+        );
+
+        position.update_atr(dec!(4));
+        assert_eq!(position.volatility_stop_price(), Some(dec!(106)));
     }
 }
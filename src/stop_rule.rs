@@ -0,0 +1,147 @@
+use crate::PositionType;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Moves `cut_loss_price` as the price moves in favour of the position.
+/// Distances are price distances from the average open price. The stop is
+/// only ever tightened.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum StopRule {
+    /// Once the price is `trigger` past the open price, moves the stop to
+    /// break-even: the open price plus the fees paid per unit and `offset`.
+    BreakEven { trigger: Decimal, offset: Decimal },
+    /// Once the price is `trigger` past the open price, locks the last
+    /// reached level (`trigger` plus whole `step`s) minus `distance`.
+    Ratchet {
+        trigger: Decimal,
+        step: Decimal,
+        distance: Decimal,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopModificationReason {
+    BreakEven,
+    Ratchet,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StopModification {
+    pub previous: Option<Decimal>,
+    pub new: Decimal,
+    pub reason: StopModificationReason,
+    pub timestamp: i64,
+}
+
+impl StopRule {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            StopRule::BreakEven { trigger, offset } => {
+                *trigger > Decimal::ZERO && !offset.is_sign_negative()
+            }
+            StopRule::Ratchet {
+                trigger,
+                step,
+                distance,
+            } => *trigger > Decimal::ZERO && *step > Decimal::ZERO && !distance.is_sign_negative(),
+        }
+    }
+
+    pub fn reason(&self) -> StopModificationReason {
+        match self {
+            StopRule::BreakEven { .. } => StopModificationReason::BreakEven,
+            StopRule::Ratchet { .. } => StopModificationReason::Ratchet,
+        }
+    }
+
+    /// Stop required by the rule at `price`, or `None` before it triggers.
+    pub(crate) fn stop_price(
+        &self,
+        position_type: &PositionType,
+        open_price: Decimal,
+        fee_per_unit: Decimal,
+        price: Decimal,
+    ) -> Option<Decimal> {
+        let sign = match position_type {
+            PositionType::Long => Decimal::ONE,
+            PositionType::Short => Decimal::NEGATIVE_ONE,
+        };
+        let favorable_move = (price - open_price) * sign;
+
+        match self {
+            StopRule::BreakEven { trigger, offset } => {
+                if favorable_move < *trigger {
+                    return None;
+                }
+                Some(open_price + sign * (fee_per_unit + offset))
+            }
+            StopRule::Ratchet {
+                trigger,
+                step,
+                distance,
+            } => {
+                if favorable_move < *trigger || step.is_zero() {
+                    return None;
+                }
+                let steps = ((favorable_move - trigger) / step).floor();
+                Some(open_price + sign * (trigger + steps * step - distance))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn break_even_covers_fees_and_offset_once_triggered() {
+        let rule = StopRule::BreakEven {
+            trigger: dec!(5),
+            offset: dec!(1),
+        };
+        let long = PositionType::Long;
+        let short = PositionType::Short;
+        assert_eq!(
+            rule.stop_price(&long, dec!(100), dec!(0.5), dec!(104)),
+            None
+        );
+        assert_eq!(
+            rule.stop_price(&long, dec!(100), dec!(0.5), dec!(105)),
+            Some(dec!(101.5))
+        );
+        assert_eq!(
+            rule.stop_price(&short, dec!(100), dec!(0.5), dec!(96)),
+            None
+        );
+        assert_eq!(
+            rule.stop_price(&short, dec!(100), dec!(0.5), dec!(95)),
+            Some(dec!(98.5))
+        );
+    }
+
+    #[test]
+    fn ratchet_locks_the_last_reached_step() {
+        let rule = StopRule::Ratchet {
+            trigger: dec!(5),
+            step: dec!(2),
+            distance: dec!(3),
+        };
+        let long = PositionType::Long;
+        let short = PositionType::Short;
+        assert_eq!(rule.stop_price(&long, dec!(100), dec!(0), dec!(104)), None);
+        assert_eq!(
+            rule.stop_price(&long, dec!(100), dec!(0), dec!(105)),
+            Some(dec!(102))
+        );
+        assert_eq!(
+            rule.stop_price(&long, dec!(100), dec!(0), dec!(108.9)),
+            Some(dec!(104))
+        );
+        assert_eq!(
+            rule.stop_price(&short, dec!(100), dec!(0), dec!(92)),
+            Some(dec!(96))
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use crate::PositionType;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// One target of a take-profit ladder: scale out `fraction` of the position
+/// once the price reaches `price`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TakeProfitLevel {
+    pub price: Decimal,
+    pub fraction: Decimal,
+}
+
+impl TakeProfitLevel {
+    pub fn new(price: Decimal, fraction: Decimal) -> Self {
+        Self { price, fraction }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.price > Decimal::ZERO && self.fraction > Decimal::ZERO && self.fraction <= Decimal::ONE
+    }
+
+    pub(crate) fn is_reached(&self, position_type: &PositionType, price: Decimal) -> bool {
+        match position_type {
+            PositionType::Long => price >= self.price,
+            PositionType::Short => price <= self.price,
+        }
+    }
+}
+
+/// A ladder level and its progress on the position.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TakeProfitTarget {
+    pub level: TakeProfitLevel,
+    /// When the price first reached the level.
+    pub hit_timestamp: Option<i64>,
+    /// Amount of the decreases attributed to the level.
+    pub reduced_amount: Decimal,
+}
+
+impl TakeProfitTarget {
+    pub(crate) fn new(level: TakeProfitLevel) -> Self {
+        Self {
+            level,
+            hit_timestamp: None,
+            reduced_amount: Decimal::ZERO,
+        }
+    }
+
+    pub fn is_hit(&self) -> bool {
+        self.hit_timestamp.is_some()
+    }
+
+    /// Amount still to reduce at this level, given the size of the position
+    /// before the scale-out started.
+    pub(crate) fn remaining_amount(&self, base_amount: Decimal) -> Decimal {
+        (base_amount * self.level.fraction - self.reduced_amount).max(Decimal::ZERO)
+    }
+}
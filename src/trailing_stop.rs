@@ -0,0 +1,114 @@
+use crate::PositionType;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// How far the trailing stop follows the best price (peak for longs,
+/// trough for shorts).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TrailingStopPolicy {
+    /// `factor` times the expected profit ratio between the open price and
+    /// the take-profit price, applied as a ratio of the peak.
+    PercentOfExpectedProfit { factor: Decimal },
+    /// Fixed ratio of the peak, e.g. 0.01 for 1%.
+    FixedPercent { ratio: Decimal },
+    /// Fixed price distance from the peak.
+    FixedDistance { distance: Decimal },
+    /// `multiple` times the ATR of the position.
+    AtrMultiple { multiple: Decimal },
+    /// The peak is rounded down to whole `step`s from the activation price
+    /// and the stop trails it by `distance`, so the stop moves in steps.
+    Step { step: Decimal, distance: Decimal },
+}
+
+impl Default for TrailingStopPolicy {
+    fn default() -> Self {
+        TrailingStopPolicy::PercentOfExpectedProfit {
+            factor: Decimal::new(5, 1),
+        }
+    }
+}
+
+/// Price from which the peak starts being tracked.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum TrailingActivation {
+    #[default]
+    TakeProfit,
+    Price(Decimal),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TrailingStop {
+    pub policy: TrailingStopPolicy,
+    pub activation: TrailingActivation,
+}
+
+/// Inputs of a trailing stop computation that come from the position.
+pub(crate) struct TrailingContext {
+    pub position_type: PositionType,
+    pub open_price: Decimal,
+    pub take_profit_price: Option<Decimal>,
+    pub activation_price: Option<Decimal>,
+    pub atr: Option<Decimal>,
+}
+
+impl TrailingStop {
+    pub fn new(policy: TrailingStopPolicy, activation: TrailingActivation) -> Self {
+        Self { policy, activation }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let activation_valid = match self.activation {
+            TrailingActivation::TakeProfit => true,
+            TrailingActivation::Price(price) => price > Decimal::ZERO,
+        };
+        let policy_valid = match &self.policy {
+            TrailingStopPolicy::PercentOfExpectedProfit { factor } => *factor > Decimal::ZERO,
+            TrailingStopPolicy::FixedPercent { ratio } => {
+                *ratio > Decimal::ZERO && *ratio < Decimal::ONE
+            }
+            TrailingStopPolicy::FixedDistance { distance } => *distance > Decimal::ZERO,
+            TrailingStopPolicy::AtrMultiple { multiple } => *multiple > Decimal::ZERO,
+            TrailingStopPolicy::Step { step, distance } => {
+                *step > Decimal::ZERO && !distance.is_sign_negative()
+            }
+        };
+        activation_valid && policy_valid
+    }
+
+    pub(crate) fn activation_price(&self, take_profit_price: Option<Decimal>) -> Option<Decimal> {
+        match self.activation {
+            TrailingActivation::TakeProfit => take_profit_price,
+            TrailingActivation::Price(price) => Some(price),
+        }
+    }
+
+    /// Stop price for the given peak, or `None` when the policy lacks an
+    /// input (take-profit price, ATR).
+    pub(crate) fn stop_price(&self, ctx: &TrailingContext, peak: Decimal) -> Option<Decimal> {
+        let sign = match ctx.position_type {
+            PositionType::Long => Decimal::ONE,
+            PositionType::Short => Decimal::NEGATIVE_ONE,
+        };
+
+        match &self.policy {
+            TrailingStopPolicy::PercentOfExpectedProfit { factor } => {
+                let expected_profit = (ctx.take_profit_price? - ctx.open_price) * sign;
+                let ratio = expected_profit / ctx.open_price * factor;
+                Some(peak * (Decimal::ONE - sign * ratio))
+            }
+            TrailingStopPolicy::FixedPercent { ratio } => {
+                Some(peak * (Decimal::ONE - sign * ratio))
+            }
+            TrailingStopPolicy::FixedDistance { distance } => Some(peak - sign * distance),
+            TrailingStopPolicy::AtrMultiple { multiple } => Some(peak - sign * multiple * ctx.atr?),
+            TrailingStopPolicy::Step { step, distance } => {
+                if step.is_zero() {
+                    return None;
+                }
+                let anchor = ctx.activation_price?;
+                let steps = ((peak - anchor) * sign / step).floor();
+                Some(anchor + sign * (steps * step - distance))
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::PositionType;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Exits driven by the ATR of the position, which the caller can refresh
+/// with `Position::update_atr`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum VolatilityStop {
+    /// Stop `multiple` ATRs behind the high-water mark (low-water mark for
+    /// shorts).
+    Chandelier { multiple: Decimal },
+    /// Stop starting `multiple` ATRs from the entry price and following the
+    /// price at that distance, never loosened.
+    AtrTrailing { multiple: Decimal },
+    /// Widens the distance between entry and `cut_loss_price` by the ratio
+    /// of the current ATR to the entry ATR, capped at `max_factor`. The stop
+    /// is never narrowed below the configured level, and a stop moved to or
+    /// past the entry price is not scaled.
+    ScaledCutLoss { max_factor: Decimal },
+}
+
+impl VolatilityStop {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            VolatilityStop::Chandelier { multiple } | VolatilityStop::AtrTrailing { multiple } => {
+                *multiple > Decimal::ZERO
+            }
+            VolatilityStop::ScaledCutLoss { max_factor } => *max_factor >= Decimal::ONE,
+        }
+    }
+}
+
+pub(crate) fn offset(position_type: &PositionType, price: Decimal, distance: Decimal) -> Decimal {
+    match position_type {
+        PositionType::Long => price - distance,
+        PositionType::Short => price + distance,
+    }
+}
+
+/// The stop that is closer to the price.
+pub(crate) fn tighter(position_type: &PositionType, a: Decimal, b: Decimal) -> Decimal {
+    match position_type {
+        PositionType::Long => a.max(b),
+        PositionType::Short => a.min(b),
+    }
+}
+
+pub(crate) fn is_stop_hit(position_type: &PositionType, price: Decimal, stop: Decimal) -> bool {
+    match position_type {
+        PositionType::Long => price <= stop,
+        PositionType::Short => price >= stop,
+    }
+}